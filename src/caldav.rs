@@ -0,0 +1,635 @@
+// Minimal read-only CalDAV server (RFC 4791) with the timetable and the exams as calendars.
+// Clients authenticate with HTTP Basic auth: the username plus an app password from
+// /caldav_password, which is the AES encrypted login data and only valid for /caldav.
+//
+//   /caldav/                      principal
+//   /caldav/calendars/            calendar home
+//   /caldav/calendars/<calendar>/ calendar collection ("timetable" or "exams")
+//   /caldav/calendars/<calendar>/<uid>.ics
+
+use std::{
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LOCATION, WWW_AUTHENTICATE},
+        HeaderMap, Method, Uri,
+    },
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use base64::prelude::*;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use fnv::FnvHasher;
+use http::StatusCode;
+use lazy_static::lazy_static;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+
+use crate::{
+    cache_stuff::TtlCache,
+    campus_backend::{login::cdlogin_get_jcookie_and_meta, req_client_funcs::get_client_default},
+    color_stuff::ColorScheme,
+    encryption::{decrypt, encrypt},
+    exam_calendar::{exam_event_to_ics, fetch_exam_calendar_events},
+    ics_stuff::{IcsCalendar, IcsEvent},
+    stundenplan::fetch_timetable,
+    time_stuff::berlin_now,
+    types::{
+        CalDavAppPassword, CalDavPasswordResponse, CampusLoginData, CdAuthData, ResponseError,
+        TimetableEvent, TimetableEventStatus,
+    },
+};
+
+const CALDAV_SCOPE: &str = "caldav";
+const CALDAV_ROOT: &str = "/caldav/";
+const CALDAV_HOME: &str = "/caldav/calendars/";
+const APP_PASSWORD_VALID_DAYS: i64 = 365;
+// CalDAV clients poll often, neither the login nor the calendars are redone for every request
+const SESSION_TTL: Duration = Duration::from_secs(15 * 60);
+const CALENDAR_TTL: Duration = Duration::from_secs(5 * 60);
+const MAX_CACHED_USERS: usize = 1000;
+// timetable window that is synced
+const TIMETABLE_PAST_DAYS: i64 = 28;
+const TIMETABLE_FUTURE_DAYS: i64 = 182;
+
+lazy_static! {
+    // keyed by a hash of the app password
+    static ref SESSIONS: TtlCache<CdAuthData> = TtlCache::new(SESSION_TTL, MAX_CACHED_USERS);
+    // "<user>/<calendar>"
+    static ref CALENDARS: TtlCache<Vec<CalDavItem>> =
+        TtlCache::new(CALENDAR_TTL, MAX_CACHED_USERS * 2);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CalDavCalendar {
+    Timetable,
+    Exams,
+}
+
+impl CalDavCalendar {
+    const ALL: [CalDavCalendar; 2] = [CalDavCalendar::Timetable, CalDavCalendar::Exams];
+
+    fn from_slug(slug: &str) -> Option<Self> {
+        CalDavCalendar::ALL
+            .into_iter()
+            .find(|calendar| calendar.slug() == slug)
+    }
+
+    fn slug(self) -> &'static str {
+        match self {
+            CalDavCalendar::Timetable => "timetable",
+            CalDavCalendar::Exams => "exams",
+        }
+    }
+
+    fn display_name(self) -> &'static str {
+        match self {
+            CalDavCalendar::Timetable => "CampusDual Stundenplan",
+            CalDavCalendar::Exams => "CampusDual Prüfungen",
+        }
+    }
+
+    fn href(self) -> String {
+        format!("{CALDAV_HOME}{}/", self.slug())
+    }
+}
+
+enum CalDavResource {
+    Principal,
+    Home,
+    Calendar(CalDavCalendar),
+    Event(CalDavCalendar, String),
+}
+
+impl CalDavResource {
+    fn from_path(path: &str) -> Option<Self> {
+        let segments = path
+            .trim_start_matches("/caldav")
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+
+        match segments.as_slice() {
+            [] => Some(CalDavResource::Principal),
+            ["calendars"] => Some(CalDavResource::Home),
+            ["calendars", calendar] => {
+                CalDavCalendar::from_slug(calendar).map(CalDavResource::Calendar)
+            }
+            ["calendars", calendar, event] if event.ends_with(".ics") => {
+                CalDavCalendar::from_slug(calendar)
+                    .map(|calendar| CalDavResource::Event(calendar, event.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CalDavItem {
+    href: String,
+    etag: String,
+    event: IcsEvent,
+}
+
+impl CalDavItem {
+    fn new(calendar: CalDavCalendar, event: IcsEvent) -> Self {
+        let name = event.uid.split('@').next().unwrap_or_default();
+        CalDavItem {
+            href: format!("{}{name}.ics", calendar.href()),
+            etag: event.etag(),
+            event,
+        }
+    }
+
+    fn ics(&self, calendar: CalDavCalendar) -> String {
+        let mut ics = IcsCalendar::new(calendar.display_name());
+        ics.publish = false;
+        ics.events.push(self.event.clone());
+        ics.render()
+    }
+}
+
+// creates the app password for the logged in user
+pub async fn post_caldav_password(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+) -> Result<Json<CalDavPasswordResponse>, ResponseError> {
+    let expires = Utc::now() + chrono::Duration::days(APP_PASSWORD_VALID_DAYS);
+    let app_password = serde_json::to_string(&CalDavAppPassword {
+        scope: CALDAV_SCOPE.to_string(),
+        user: cd_auth_data.user.clone(),
+        password: cd_auth_data.password,
+        expires,
+    })?;
+    let (nonce, cipher) = encrypt(&app_password).map_err(|status_code| ResponseError {
+        message: "Internal Server Error".to_string(),
+        status_code,
+    })?;
+
+    Ok(Json(CalDavPasswordResponse {
+        url: CALDAV_ROOT.to_string(),
+        username: cd_auth_data.user,
+        password: format!("{nonce}.{cipher}"),
+        expires,
+    }))
+}
+
+// RFC 6764 discovery
+pub async fn get_well_known_caldav() -> Response {
+    (StatusCode::MOVED_PERMANENTLY, [(LOCATION, CALDAV_ROOT)]).into_response()
+}
+
+// all methods end up here, axum has no routing for PROPFIND and REPORT
+pub async fn caldav(method: Method, uri: Uri, headers: HeaderMap, body: String) -> Response {
+    if method == Method::OPTIONS {
+        return (
+            StatusCode::OK,
+            [
+                ("DAV", "1, 3, calendar-access"),
+                ("Allow", "OPTIONS, GET, HEAD, PROPFIND, REPORT"),
+            ],
+        )
+            .into_response();
+    }
+
+    let cd_auth_data = match authenticate(&headers).await {
+        Ok(cd_auth_data) => cd_auth_data,
+        Err(response) => return response,
+    };
+    let Some(resource) = CalDavResource::from_path(uri.path()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let result = match method.as_str() {
+        "PROPFIND" => propfind(&cd_auth_data, &resource, &headers).await,
+        "REPORT" => report(&cd_auth_data, &resource, &body).await,
+        "GET" | "HEAD" => get(&cd_auth_data, &resource, &headers, method == Method::HEAD).await,
+        // the calendars mirror CampusDual, nothing can be changed through CalDAV
+        "PUT" | "DELETE" | "PROPPATCH" | "MKCALENDAR" | "MKCOL" | "MOVE" | "COPY" => {
+            Ok(StatusCode::FORBIDDEN.into_response())
+        }
+        _ => Ok(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+    };
+
+    result.unwrap_or_else(|e| e.into_response())
+}
+
+async fn authenticate(headers: &HeaderMap) -> Result<CdAuthData, Response> {
+    let unauthorized = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(
+                WWW_AUTHENTICATE,
+                "Basic realm=\"campus-api CalDAV\", charset=\"UTF-8\"",
+            )],
+        )
+            .into_response()
+    };
+
+    let (user, app_password) = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Basic "))
+        .and_then(|credentials| BASE64_STANDARD.decode(credentials.trim()).ok())
+        .and_then(|credentials| String::from_utf8(credentials).ok())
+        .and_then(|credentials| {
+            credentials
+                .split_once(':')
+                .map(|(user, password)| (user.to_string(), password.to_string()))
+        })
+        .ok_or_else(unauthorized)?;
+
+    let session_key = {
+        let mut hasher = FnvHasher::default();
+        app_password.hash(&mut hasher);
+        format!("{user}/{:016x}", hasher.finish())
+    };
+    if let Some(cd_auth_data) = SESSIONS.get(&session_key) {
+        return Ok(cd_auth_data);
+    }
+
+    let app_password = app_password
+        .split_once('.')
+        .and_then(|(nonce, cipher)| decrypt(nonce, cipher).ok())
+        .and_then(|plain| serde_json::from_str::<CalDavAppPassword>(&plain).ok())
+        .filter(|app_password| {
+            app_password.scope == CALDAV_SCOPE
+                && app_password.user == user
+                && app_password.expires > Utc::now()
+        })
+        .ok_or_else(unauthorized)?;
+
+    let (cd_auth_data, _) = cdlogin_get_jcookie_and_meta(CampusLoginData {
+        username: app_password.user,
+        password: app_password.password,
+    })
+    .await
+    .map_err(|_| unauthorized())?;
+
+    SESSIONS.insert(session_key, cd_auth_data.clone());
+    Ok(cd_auth_data)
+}
+
+async fn load_calendar(
+    cd_auth_data: &CdAuthData,
+    calendar: CalDavCalendar,
+) -> Result<Vec<CalDavItem>, ResponseError> {
+    let cache_key = format!("{}/{}", cd_auth_data.user, calendar.slug());
+    if let Some(items) = CALENDARS.get(&cache_key) {
+        return Ok(items);
+    }
+
+    let events = match calendar {
+        CalDavCalendar::Timetable => {
+            let now = berlin_now();
+            let client = get_client_default(true)?;
+            fetch_timetable(
+                &client,
+                cd_auth_data,
+                &(now - chrono::Duration::days(TIMETABLE_PAST_DAYS)),
+                &(now + chrono::Duration::days(TIMETABLE_FUTURE_DAYS)),
+                &ColorScheme::default(),
+            )
+            .await?
+            .iter()
+            .map(timetable_event_to_ics)
+            .collect::<Vec<_>>()
+        }
+        CalDavCalendar::Exams => fetch_exam_calendar_events(cd_auth_data)
+            .await?
+            .iter()
+            .map(exam_event_to_ics)
+            .collect(),
+    };
+
+    // parallel groups can produce the same lecture twice
+    let mut items: Vec<CalDavItem> = Vec::with_capacity(events.len());
+    for event in events {
+        if !items.iter().any(|item| item.event.uid == event.uid) {
+            items.push(CalDavItem::new(calendar, event));
+        }
+    }
+
+    CALENDARS.insert(cache_key, items.clone());
+    Ok(items)
+}
+
+// the uid has to survive room and remark changes, so only title and time go into it
+fn timetable_event_to_ics(timetable_event: &TimetableEvent) -> IcsEvent {
+    let mut hasher = FnvHasher::default();
+    timetable_event.title.hash(&mut hasher);
+    timetable_event.start.timestamp().hash(&mut hasher);
+    timetable_event.end.timestamp().hash(&mut hasher);
+
+    let mut event = IcsEvent::new(
+        format!("tt-{:016x}@campus-api", hasher.finish()),
+        match timetable_event.status {
+            TimetableEventStatus::Cancelled => format!("Entfällt: {}", timetable_event.title),
+            _ => timetable_event.title.clone(),
+        },
+        &timetable_event.start,
+    );
+    event.end = Some(timetable_event.end.with_timezone(&Utc));
+    event.location = timetable_event.room.as_ref().map(|room| room.long.clone());
+
+    let description = [
+        Some(timetable_event.module_title.clone()),
+        Some(timetable_event.instructors.join(", ")),
+        timetable_event.remarks.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|line| !line.trim().is_empty())
+    .collect::<Vec<_>>();
+    event.description = (!description.is_empty()).then(|| description.join("\n"));
+    event.categories = timetable_event.module_code.iter().cloned().collect();
+
+    event
+}
+
+async fn propfind(
+    cd_auth_data: &CdAuthData,
+    resource: &CalDavResource,
+    headers: &HeaderMap,
+) -> Result<Response, ResponseError> {
+    // "infinity" is treated like 1, the tree is only three levels deep anyway
+    let depth_one = headers
+        .get("Depth")
+        .and_then(|depth| depth.to_str().ok())
+        .is_none_or(|depth| depth != "0");
+
+    let mut responses = Vec::new();
+    match resource {
+        CalDavResource::Principal => {
+            responses.push(prop_response(CALDAV_ROOT, &principal_props(cd_auth_data)));
+            if depth_one {
+                responses.push(prop_response(CALDAV_HOME, &home_props()));
+            }
+        }
+        CalDavResource::Home => {
+            responses.push(prop_response(CALDAV_HOME, &home_props()));
+            if depth_one {
+                for calendar in CalDavCalendar::ALL {
+                    let items = load_calendar(cd_auth_data, calendar).await?;
+                    responses.push(prop_response(
+                        &calendar.href(),
+                        &calendar_props(calendar, &items),
+                    ));
+                }
+            }
+        }
+        CalDavResource::Calendar(calendar) => {
+            let items = load_calendar(cd_auth_data, *calendar).await?;
+            responses.push(prop_response(
+                &calendar.href(),
+                &calendar_props(*calendar, &items),
+            ));
+            if depth_one {
+                responses.extend(
+                    items
+                        .iter()
+                        .map(|item| prop_response(&item.href, &item_props(item, None))),
+                );
+            }
+        }
+        CalDavResource::Event(calendar, _) => {
+            let items = load_calendar(cd_auth_data, *calendar).await?;
+            let Some(item) = find_item(&items, resource) else {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            };
+            responses.push(prop_response(&item.href, &item_props(item, None)));
+        }
+    }
+
+    Ok(multistatus(&responses))
+}
+
+async fn report(
+    cd_auth_data: &CdAuthData,
+    resource: &CalDavResource,
+    body: &str,
+) -> Result<Response, ResponseError> {
+    lazy_static! {
+        static ref HREF_RE: Regex =
+            Regex::new(r"<(?:[A-Za-z0-9]+:)?href[^>]*>\s*([^<]+?)\s*</(?:[A-Za-z0-9]+:)?href>")
+                .unwrap();
+        static ref TIME_RANGE_RE: Regex =
+            Regex::new(r#"<(?:[A-Za-z0-9]+:)?time-range([^>]*)/?>"#).unwrap();
+        static ref ATTRIBUTE_RE: Regex = Regex::new(r#"(start|end)\s*=\s*"([0-9TZ]+)""#).unwrap();
+    }
+
+    let calendar = match resource {
+        CalDavResource::Calendar(calendar) => *calendar,
+        _ => return Ok(StatusCode::FORBIDDEN.into_response()),
+    };
+    let items = load_calendar(cd_auth_data, calendar).await?;
+    let with_data = body.contains("calendar-data");
+
+    let responses = if body.contains("calendar-multiget") {
+        HREF_RE
+            .captures_iter(body)
+            .map(|captures| {
+                let href = captures[1].to_string();
+                match items.iter().find(|item| same_href(&item.href, &href)) {
+                    Some(item) => prop_response(
+                        &item.href,
+                        &item_props(item, with_data.then(|| item.ics(calendar)).as_deref()),
+                    ),
+                    None => format!(
+                        "<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>",
+                        xml_escape(&href)
+                    ),
+                }
+            })
+            .collect::<Vec<_>>()
+    } else if body.contains("calendar-query") {
+        // only the time-range filter is supported, every item is a VEVENT anyway
+        let (mut start, mut end) = (None, None);
+        if let Some(time_range) = TIME_RANGE_RE.captures(body) {
+            for attribute in ATTRIBUTE_RE.captures_iter(&time_range[1]) {
+                let value = parse_ical_utc(&attribute[2]);
+                match &attribute[1] {
+                    "start" => start = value,
+                    _ => end = value,
+                }
+            }
+        }
+
+        items
+            .iter()
+            .filter(|item| {
+                let item_end = item.event.end.unwrap_or(item.event.start);
+                start.is_none_or(|start| item_end > start)
+                    && end.is_none_or(|end| item.event.start < end)
+            })
+            .map(|item| {
+                prop_response(
+                    &item.href,
+                    &item_props(item, with_data.then(|| item.ics(calendar)).as_deref()),
+                )
+            })
+            .collect()
+    } else {
+        // e.g. sync-collection, clients fall back to ctag + etags
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+
+    Ok(multistatus(&responses))
+}
+
+async fn get(
+    cd_auth_data: &CdAuthData,
+    resource: &CalDavResource,
+    headers: &HeaderMap,
+    head: bool,
+) -> Result<Response, ResponseError> {
+    let (ics, etag) = match resource {
+        CalDavResource::Calendar(calendar) => {
+            let items = load_calendar(cd_auth_data, *calendar).await?;
+            let mut ics = IcsCalendar::new(calendar.display_name());
+            ics.events = items.iter().map(|item| item.event.clone()).collect();
+            (ics.render(), calendar_ctag(&items))
+        }
+        CalDavResource::Event(calendar, _) => {
+            let items = load_calendar(cd_auth_data, *calendar).await?;
+            let Some(item) = find_item(&items, resource) else {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            };
+            (item.ics(*calendar), item.etag.clone())
+        }
+        _ => return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+    };
+
+    if headers
+        .get(IF_NONE_MATCH)
+        .and_then(|header| header.to_str().ok())
+        .is_some_and(|if_none_match| if_none_match == etag)
+    {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+    }
+
+    let body = if head { Body::empty() } else { Body::from(ics) };
+    Ok((
+        StatusCode::OK,
+        [
+            (CONTENT_TYPE, "text/calendar; charset=utf-8".to_string()),
+            (ETAG, etag),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn find_item<'a>(items: &'a [CalDavItem], resource: &CalDavResource) -> Option<&'a CalDavItem> {
+    let CalDavResource::Event(calendar, name) = resource else {
+        return None;
+    };
+    let href = format!("{}{name}", calendar.href());
+    items.iter().find(|item| item.href == href)
+}
+
+// clients may send absolute URLs or percent-encode the href
+fn same_href(item_href: &str, requested: &str) -> bool {
+    let decode = |href: &str| percent_decode_str(href).decode_utf8_lossy().into_owned();
+    let (item_href, requested) = (decode(item_href), decode(requested));
+    requested == item_href || requested.ends_with(&item_href)
+}
+
+fn principal_props(cd_auth_data: &CdAuthData) -> String {
+    format!(
+        "<d:resourcetype><d:collection/><d:principal/></d:resourcetype>\
+         <d:displayname>{}</d:displayname>\
+         <d:current-user-principal><d:href>{CALDAV_ROOT}</d:href></d:current-user-principal>\
+         <d:principal-URL><d:href>{CALDAV_ROOT}</d:href></d:principal-URL>\
+         <c:calendar-home-set><d:href>{CALDAV_HOME}</d:href></c:calendar-home-set>",
+        xml_escape(&cd_auth_data.user)
+    )
+}
+
+fn home_props() -> String {
+    format!(
+        "<d:resourcetype><d:collection/></d:resourcetype>\
+         <d:displayname>CampusDual</d:displayname>\
+         <d:current-user-principal><d:href>{CALDAV_ROOT}</d:href></d:current-user-principal>"
+    )
+}
+
+fn calendar_props(calendar: CalDavCalendar, items: &[CalDavItem]) -> String {
+    let ctag = calendar_ctag(items);
+    format!(
+        "<d:resourcetype><d:collection/><c:calendar/></d:resourcetype>\
+         <d:displayname>{}</d:displayname>\
+         <c:supported-calendar-component-set><c:comp name=\"VEVENT\"/></c:supported-calendar-component-set>\
+         <d:current-user-principal><d:href>{CALDAV_ROOT}</d:href></d:current-user-principal>\
+         <d:current-user-privilege-set><d:privilege><d:read/></d:privilege></d:current-user-privilege-set>\
+         <cs:getctag>{}</cs:getctag>\
+         <d:getetag>{}</d:getetag>",
+        xml_escape(calendar.display_name()),
+        xml_escape(&ctag),
+        xml_escape(&ctag)
+    )
+}
+
+fn item_props(item: &CalDavItem, calendar_data: Option<&str>) -> String {
+    let mut props = format!(
+        "<d:resourcetype/>\
+         <d:getcontenttype>text/calendar; charset=utf-8; component=vevent</d:getcontenttype>\
+         <d:getetag>{}</d:getetag>",
+        xml_escape(&item.etag)
+    );
+    if let Some(calendar_data) = calendar_data {
+        props.push_str(&format!(
+            "<c:calendar-data>{}</c:calendar-data>",
+            xml_escape(calendar_data)
+        ));
+    }
+    props
+}
+
+// changes whenever any event of the calendar does
+fn calendar_ctag(items: &[CalDavItem]) -> String {
+    let mut hasher = FnvHasher::default();
+    for item in items {
+        item.etag.hash(&mut hasher);
+    }
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+fn prop_response(href: &str, props: &str) -> String {
+    format!(
+        "<d:response><d:href>{}</d:href><d:propstat><d:prop>{props}</d:prop>\
+         <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        xml_escape(href)
+    )
+}
+
+fn multistatus(responses: &[String]) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <d:multistatus xmlns:d=\"DAV:\" xmlns:c=\"urn:ietf:params:xml:ns:caldav\" xmlns:cs=\"http://calendarserver.org/ns/\">{}</d:multistatus>",
+        responses.concat()
+    );
+    (
+        StatusCode::MULTI_STATUS,
+        [(CONTENT_TYPE, "application/xml; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// "20241019T000000Z"
+fn parse_ical_utc(raw: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(raw.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        .ok()
+        .map(|date_time| date_time.and_utc())
+}
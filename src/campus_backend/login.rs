@@ -5,6 +5,7 @@ use std::{sync::Arc, time::Instant};
 use lazy_static::lazy_static;
 use reqwest::Client;
 use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use reqwest_middleware::ClientWithMiddleware;
 use scraper::{Html, Selector};
 
 use crate::{
@@ -31,6 +32,7 @@ pub async fn cdlogin_get_jcookie_and_meta(
         hash,
         user: login_data.username,
         password: login_data.password,
+        seminar_group: user_basic_info.seminar_group.clone(),
     };
 
     Ok((cd_auth_data, user_basic_info))
@@ -103,7 +105,6 @@ fn extract_cd_cookie(cookie_store: Arc<CookieStoreMutex>) -> Result<String> {
 
 pub async fn get_hash_and_userinfo(client: &Client) -> Result<(String, UserBasicInfo)> {
     let whole = Instant::now();
-    let mut user_basic_info = UserBasicInfo::default();
 
     let resp = client
         .get("https://selfservice.campus-dual.de/index/login")
@@ -116,6 +117,29 @@ pub async fn get_hash_and_userinfo(client: &Client) -> Result<(String, UserBasic
     println!("get hash and user info req: {:.2?}", whole.elapsed());
     let now = Instant::now();
 
+    let hash_and_userinfo = parse_hash_and_userinfo(&resp)?;
+
+    println!("get hash and user info parsing: {:.2?}", now.elapsed());
+
+    Ok(hash_and_userinfo)
+}
+
+// same as above, but for an already authenticated session (cookie client)
+pub async fn get_userinfo_with_cd_cookie(client: &ClientWithMiddleware) -> Result<UserBasicInfo> {
+    let resp = client
+        .get("https://selfservice.campus-dual.de/index/login")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(parse_hash_and_userinfo(&resp)?.1)
+}
+
+fn parse_hash_and_userinfo(resp: &str) -> Result<(String, UserBasicInfo)> {
+    let mut user_basic_info = UserBasicInfo::default();
+
     lazy_static! {
         static ref RE_HASH: Regex = Regex::new(r#"hash="(\w+)";user="(\d+)";"#).unwrap();
         static ref RE_STUDI: Regex = Regex::new(r#"<strong>Name:\s*</strong>(\w+),\s*(\w+).*<strong>\s*Seminargruppe:\s*</strong>([\w-]*).*<br>(.*)"#).unwrap();
@@ -123,14 +147,14 @@ pub async fn get_hash_and_userinfo(client: &Client) -> Result<(String, UserBasic
 
     let hash: String;
 
-    if let Some(captures) = RE_HASH.captures(&resp) {
+    if let Some(captures) = RE_HASH.captures(resp) {
         hash = captures.get(1).unwrap().as_str().to_string();
         user_basic_info.user = captures.get(2).unwrap().as_str().to_string();
     } else {
         return Err(anyhow::anyhow!("Hash not found"));
     }
 
-    if let Some(captures) = RE_STUDI.captures(&resp) {
+    if let Some(captures) = RE_STUDI.captures(resp) {
         user_basic_info.last_name = captures.get(1).unwrap().as_str().to_string();
         user_basic_info.first_name = captures.get(2).unwrap().as_str().to_string();
         user_basic_info.seminar_group = captures.get(3).unwrap().as_str().to_string();
@@ -144,7 +168,5 @@ pub async fn get_hash_and_userinfo(client: &Client) -> Result<(String, UserBasic
         }
     }
 
-    println!("get hash and user info parsing: {:.2?}", now.elapsed());
-
     Ok((hash, user_basic_info))
 }
@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{cmp::Ordering, sync::Arc};
 
 use anyhow::{Context, Result};
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveTime};
 use cookie_store::CookieStore;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -9,13 +9,15 @@ use reqwest::Url;
 use reqwest_cookie_store::CookieStoreMutex;
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use scraper::{selectable::Selectable, Html, Selector};
+use scraper::{selectable::Selectable, ElementRef, Html, Selector};
 
 use crate::{
     constants::CD_CERT_PEM,
     types::{
-        CampusDualGrade, CampusDualSignupOption, CampusDualSubGrade, CampusDualVerfahrenOption,
-        ExamRegistrationMetadata, GradeResultsTableType, SubGradeMetadata,
+        parse_cd_date, AcademicPeriod, CampusDualGrade, CampusDualSignupOption, CampusDualSubGrade,
+        CampusDualVerfahrenOption, ExamRegistrationMetadata, ExamRoom, ExamStatus,
+        ExamTableExtraction, GradeResultsTableType, GradeValue, GradesExtraction, ParserWarning,
+        SubGradeMetadata,
     },
 };
 
@@ -55,17 +57,15 @@ pub fn get_client_with_cd_cookie(retry: bool, j_cookie: String) -> Result<Client
     .build())
 }
 
-pub fn extract_grades(html_text: String) -> Result<Vec<CampusDualGrade>> {
+pub fn extract_grades(html_text: String) -> Result<GradesExtraction> {
     lazy_static! {
-        static ref IMG_SEL: Selector = Selector::parse("img").unwrap();
         static ref TABLE_SEL: Selector = Selector::parse("#acwork tbody").unwrap();
         static ref NORMAL_MODULE_SEL: Selector = Selector::parse(".child-of-node-0").unwrap();
         static ref TEILPRUEFUNG_SEL: Selector = Selector::parse(".child-of-node-1000").unwrap();
         static ref TD_SEL: Selector = Selector::parse("td").unwrap();
-        static ref METADATA_SEL: Selector = Selector::parse("td>div#mscore>a").unwrap();
     };
 
-    let mut grades = Vec::new();
+    let mut extraction = GradesExtraction::default();
 
     let document = Html::parse_document(&html_text);
     let table = document
@@ -75,372 +75,517 @@ pub fn extract_grades(html_text: String) -> Result<Vec<CampusDualGrade>> {
 
     let normal_module_lines = table.select(&NORMAL_MODULE_SEL);
     for line in normal_module_lines {
-        let l_id = line
-            .value()
-            .attr("id")
-            .context("CD: grades table line has no ID")?;
-        let mut content = line.select(&TD_SEL);
-        let table_fields = GradeResultsTableType::from(&mut content);
-
-        let name = table_fields.name_el.text().next().unwrap().to_string();
-        let grade = table_fields.grade_el.text().next().unwrap().to_string();
-        let total_passed_el_opt = table_fields.passed_el.select(&IMG_SEL).next();
+        let Some(l_id) = line.value().attr("id") else {
+            extraction.warnings.push(ParserWarning::new(
+                None,
+                "id",
+                "module row has no ID, skipped",
+            ));
+            continue;
+        };
 
-        let total_passed = total_passed_el_opt
-            .as_ref()
-            .map(|passed_el| passed_el.value().attr("src").unwrap().contains("green.png"));
+        let mut content = line.select(&TD_SEL);
+        let table_fields = match GradeResultsTableType::try_from(&mut content) {
+            Ok(table_fields) => table_fields,
+            Err(field) => {
+                extraction.warnings.push(ParserWarning::new(
+                    Some(l_id),
+                    field,
+                    "table cell missing, module skipped",
+                ));
+                continue;
+            }
+        };
 
-        let credit_points = table_fields
-            .ects_el
-            .text()
-            .next()
-            .unwrap()
-            .trim_start()
-            .parse::<i32>()
-            .unwrap_or_default();
-        let akad_period = table_fields
-            .akad_period_el
-            .text()
-            .next()
-            .unwrap()
-            .to_string();
+        let Some(name) = first_text(table_fields.name_el) else {
+            extraction.warnings.push(ParserWarning::new(
+                Some(l_id),
+                "name",
+                "module name missing, module skipped",
+            ));
+            continue;
+        };
+        let grade = first_text(table_fields.grade_el).unwrap_or_default();
+        let total_passed = passed_from_icon(table_fields.passed_el, l_id, &mut extraction.warnings);
+
+        let ects_text = first_text(table_fields.ects_el).unwrap_or_default();
+        let credit_points = match ects_text.trim().parse::<i32>() {
+            Ok(credit_points) => credit_points,
+            Err(_) => {
+                if !ects_text.trim().is_empty() {
+                    extraction.warnings.push(ParserWarning::new(
+                        Some(l_id),
+                        "credit_points",
+                        &format!("unparsable ECTS value '{ects_text}', using 0"),
+                    ));
+                }
+                0
+            }
+        };
+        let akad_period = first_text(table_fields.akad_period_el).unwrap_or_default();
 
         let mut subgrades: Vec<CampusDualSubGrade> = Vec::new();
-        for grade_subgrade_line in
-            table.select(&Selector::parse(&format!(".child-of-{}", l_id)).unwrap())
-        {
-            let mut content = grade_subgrade_line.select(&TD_SEL);
-            let sub_table_fields = GradeResultsTableType::from(&mut content);
-
-            let sub_grade = CampusDualSubGrade {
-                name: sub_table_fields
-                    .name_el
-                    .text()
-                    .next()
-                    .unwrap()
-                    .trim_start()
-                    .to_string(),
-                grade: sub_table_fields.grade_el.text().next().unwrap().to_string(),
-                passed: sub_table_fields
-                    .passed_el
-                    .select(&IMG_SEL)
-                    .next()
-                    .as_ref()
-                    .map(|passed_el| passed_el.value().attr("src").unwrap().contains("green.png")),
-                beurteilung: sub_table_fields
-                    .beurteilung_el
-                    .text()
-                    .next()
-                    .unwrap()
-                    .to_string(),
-                bekanntgabe: sub_table_fields
-                    .bekanntgabe_el
-                    .text()
-                    .next()
-                    .unwrap()
-                    .to_string(),
-                wiederholung: sub_table_fields
-                    .wiederholung_el
-                    .text()
-                    .next()
-                    .map(|s| s.to_string()),
-                akad_period: sub_table_fields
-                    .akad_period_el
-                    .text()
-                    .next()
-                    .unwrap()
-                    .to_string(),
-                internal_metadata: grade_subgrade_line.select(&METADATA_SEL).next().and_then(
-                    |internal_metadata| {
-                        let module = internal_metadata.attr("data-module")?;
-                        let peryr = internal_metadata.attr("data-peryr")?;
-                        let perid = internal_metadata.attr("data-perid")?;
-
-                        Some(SubGradeMetadata {
-                            module: module.to_string(),
-                            peryr: peryr.to_string(),
-                            perid: perid.to_string(),
-                        })
-                    },
-                ),
-            };
-            subgrades.push(sub_grade);
+        match Selector::parse(&format!(".child-of-{}", l_id)) {
+            Ok(subline_selector) => {
+                for grade_subgrade_line in table.select(&subline_selector) {
+                    let sub_id = grade_subgrade_line.value().attr("id").unwrap_or(l_id);
+                    if let Some(sub_grade) = extract_subgrade(
+                        grade_subgrade_line,
+                        sub_id,
+                        false,
+                        &mut extraction.warnings,
+                    ) {
+                        subgrades.push(sub_grade);
+                    }
+                }
+            }
+            Err(_) => extraction.warnings.push(ParserWarning::new(
+                Some(l_id),
+                "subgrades",
+                "row ID is not usable as selector, subgrades skipped",
+            )),
         }
-        grades.push(CampusDualGrade {
+
+        extraction.grades.push(CampusDualGrade {
             name,
+            grade_value: GradeValue::parse(&grade, total_passed),
             grade,
             total_passed,
             credit_points,
+            akad_period_parsed: AcademicPeriod::parse(&akad_period),
             akad_period,
             subgrades,
         });
     }
 
-    // get teilpruefungen
+    // get teilpruefungen (same column layout, but they are their own subgrade)
     for teilpruefung_line in table.select(&TEILPRUEFUNG_SEL) {
-        let content_selector = &TD_SEL;
-        let mut content = teilpruefung_line.select(content_selector);
-        let name = content.next().unwrap().text().next().unwrap().trim();
-        let grade = content.next().unwrap().text().next().unwrap();
-
-        let total_passed_el_opt = &content.next().unwrap().select(&IMG_SEL).next();
-
-        let total_passed = total_passed_el_opt
-            .as_ref()
-            .map(|passed_el| passed_el.value().attr("src").unwrap().contains("green.png"));
-        let beurteilung = content.nth(1).unwrap().text().next().unwrap().to_string();
-        let bekanntgabe = content.next().unwrap().text().next().unwrap().to_string();
-
-        let credit_points = 0;
-        let akad_period = content.nth(1).unwrap().text().next().unwrap().to_string();
-
-        let subgrades = vec![CampusDualSubGrade {
-            name: name.to_string(),
-            grade: grade.to_string(),
-            passed: total_passed,
-            beurteilung,
-            bekanntgabe,
-            wiederholung: None,
-            akad_period: akad_period.clone(),
-            internal_metadata: None,
-        }];
-
-        grades.push(CampusDualGrade {
-            name: name.to_string(),
-            grade: grade.to_string(),
-            total_passed,
-            credit_points,
-            akad_period,
-            subgrades,
+        let row_id = teilpruefung_line.value().attr("id").unwrap_or("node-1000");
+        let Some(subgrade) =
+            extract_subgrade(teilpruefung_line, row_id, true, &mut extraction.warnings)
+        else {
+            continue;
+        };
+
+        extraction.grades.push(CampusDualGrade {
+            name: subgrade.name.clone(),
+            grade: subgrade.grade.clone(),
+            grade_value: subgrade.grade_value,
+            total_passed: subgrade.passed,
+            credit_points: 0,
+            akad_period: subgrade.akad_period.clone(),
+            akad_period_parsed: subgrade.akad_period_parsed,
+            subgrades: vec![subgrade],
         });
     }
 
-    grades.sort_by(|grade_a, grade_b| {
-        get_newest_subgrade_date(grade_b).cmp(&get_newest_subgrade_date(grade_a))
+    extraction.grades.sort_by(|grade_a, grade_b| {
+        // newest first, grades without any known date last
+        match (grade_a.newest_bekanntgabe(), grade_b.newest_bekanntgabe()) {
+            (Some(date_a), Some(date_b)) => date_b.cmp(&date_a),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
     });
 
-    Ok(grades)
-}
-
-fn get_newest_subgrade_date(grade: &CampusDualGrade) -> NaiveDate {
-    let newest_subgrade = grade
-        .subgrades
-        .iter()
-        .max_by(|a, b| a.bekanntgabe.cmp(&b.bekanntgabe))
-        .unwrap();
+    for warning in &extraction.warnings {
+        log::warn!("CD grades parser: {warning:?}");
+    }
 
-    NaiveDate::parse_from_str(&newest_subgrade.bekanntgabe, "%d.%m.%Y").unwrap()
+    Ok(extraction)
 }
 
-pub async fn extract_exam_signup_options(html_text: String) -> Result<Vec<CampusDualSignupOption>> {
+fn extract_subgrade(
+    line: ElementRef,
+    row_id: &str,
+    is_teilpruefung: bool,
+    warnings: &mut Vec<ParserWarning>,
+) -> Option<CampusDualSubGrade> {
     lazy_static! {
-        static ref IMG_SEL: Selector = Selector::parse("img").unwrap();
-        static ref TABLE_SEL: Selector = Selector::parse("#expproc tbody").unwrap();
-        static ref NORMAL_LINE_SEL: Selector = Selector::parse(".child-of-node-0").unwrap();
         static ref TD_SEL: Selector = Selector::parse("td").unwrap();
-        static ref METADATA_SEL: Selector = Selector::parse("td>a.booking").unwrap();
+        static ref METADATA_SEL: Selector = Selector::parse("td>div#mscore>a").unwrap();
     };
 
-    let mut signup_options = Vec::new();
+    let mut content = line.select(&TD_SEL);
+    let sub_table_fields = match GradeResultsTableType::try_from(&mut content) {
+        Ok(sub_table_fields) => sub_table_fields,
+        Err(field) => {
+            warnings.push(ParserWarning::new(
+                Some(row_id),
+                field,
+                "table cell missing, subgrade skipped",
+            ));
+            return None;
+        }
+    };
 
-    let document = Html::parse_document(&html_text);
-    let table = document.select(&TABLE_SEL).next().unwrap();
-    let top_level_lines = table.select(&NORMAL_LINE_SEL);
-    for line in top_level_lines {
-        let l_id = line.value().attr("id").unwrap();
-        let mut content = line.select(&TD_SEL);
+    let Some(name) = first_text(sub_table_fields.name_el) else {
+        warnings.push(ParserWarning::new(
+            Some(row_id),
+            "name",
+            "subgrade name missing, subgrade skipped",
+        ));
+        return None;
+    };
+    let name = name.trim().to_string();
+
+    let grade = first_text(sub_table_fields.grade_el).unwrap_or_default();
+    let passed = passed_from_icon(sub_table_fields.passed_el, row_id, warnings);
+
+    let bekanntgabe = first_text(sub_table_fields.bekanntgabe_el).unwrap_or_default();
+    let bekanntgabe_date = parse_cd_date(&bekanntgabe);
+    if bekanntgabe_date.is_none() && !bekanntgabe.trim().is_empty() {
+        warnings.push(ParserWarning::new(
+            Some(row_id),
+            "bekanntgabe",
+            &format!("unparsable announcement date '{bekanntgabe}'"),
+        ));
+    }
 
-        let name = content.next().unwrap().text().next().unwrap().to_string();
-        let verfahren = content.next().unwrap().text().next().unwrap().to_string();
-        let pruefart = content.next().unwrap().text().next().unwrap().to_string();
-
-        let subline_selector = &Selector::parse(&format!(".child-of-{l_id}")).unwrap();
-        let mut sublines = table.select(subline_selector);
-        let main_subline = sublines.next().unwrap();
-
-        let internal_metadata =
-            main_subline
-                .select(&METADATA_SEL)
-                .next()
-                .map(|meta_el| ExamRegistrationMetadata {
-                    assessment: meta_el.value().attr("data-evob_objid").unwrap().to_string(),
-                    peryr: meta_el.value().attr("data-peryr").unwrap().to_string(),
-                    perid: meta_el.value().attr("data-perid").unwrap().to_string(),
-                    offerno: meta_el.value().attr("data-offerno").unwrap().to_string(),
-                });
+    let akad_period = first_text(sub_table_fields.akad_period_el).unwrap_or_default();
 
-        let status_icon_url = main_subline
-            .select(&IMG_SEL)
+    let internal_metadata = if is_teilpruefung {
+        None
+    } else {
+        line.select(&METADATA_SEL)
             .next()
-            .unwrap()
-            .value()
-            .attr("src")
-            .unwrap();
-        let status = match status_icon_url {
-            "/images/missed.png" => "🚫",
-            "/images/yellow.png" => "📝",
-            "/images/exclamation.jpg" => "⚠️",
-            _ => "⁉️",
-        }
-        .to_string();
+            .and_then(|internal_metadata| {
+                let module = internal_metadata.attr("data-module")?;
+                let peryr = internal_metadata.attr("data-peryr")?;
+                let perid = internal_metadata.attr("data-perid")?;
+
+                Some(SubGradeMetadata {
+                    module: module.to_string(),
+                    peryr: peryr.to_string(),
+                    perid: perid.to_string(),
+                })
+            })
+    };
 
-        // my shoddy code demands that the iterator is over owned values and not references,
-        // else the iterator doesn't consume the values and causes wrapping after the first None
-        let mut main_subline_texts = main_subline.text().collect::<Vec<_>>().into_iter();
+    Some(CampusDualSubGrade {
+        name,
+        grade_value: GradeValue::parse(&grade, passed),
+        grade,
+        passed,
+        beurteilung: first_text(sub_table_fields.beurteilung_el).unwrap_or_default(),
+        bekanntgabe_date,
+        bekanntgabe,
+        wiederholung: if is_teilpruefung {
+            None
+        } else {
+            first_text(sub_table_fields.wiederholung_el)
+        },
+        akad_period_parsed: AcademicPeriod::parse(&akad_period),
+        akad_period,
+        internal_metadata,
+    })
+}
 
-        if main_subline_texts.len() == 0 {
-            signup_options.push(CampusDualSignupOption {
-                name,
-                verfahren,
-                pruefart,
-                status,
-                signup_information: "Daten konnten nicht extrahiert werden".to_string(),
-                exam_date: None,
-                exam_time: None,
-                exam_room: None,
-                warning_message: None,
-                signup_until: None,
-                internal_metadata: None,
-            });
+fn first_text(el: ElementRef) -> Option<String> {
+    el.text().next().map(|text| text.to_string())
+}
 
-            continue;
+// green icon: passed, any other icon: failed, no icon: not graded yet
+fn passed_from_icon(
+    passed_el: ElementRef,
+    row_id: &str,
+    warnings: &mut Vec<ParserWarning>,
+) -> Option<bool> {
+    lazy_static! {
+        static ref IMG_SEL: Selector = Selector::parse("img").unwrap();
+    };
+
+    let img = passed_el.select(&IMG_SEL).next()?;
+    match img.value().attr("src") {
+        Some(src) => Some(src.contains("green.png")),
+        None => {
+            warnings.push(ParserWarning::new(
+                Some(row_id),
+                "passed",
+                "status icon has no src, status unknown",
+            ));
+            None
         }
+    }
+}
 
-        let signup_information_messy = main_subline_texts.next().unwrap().trim_start();
-        let signup_information =
-            if let Some(stripped) = signup_information_messy.strip_suffix(", Prüfungstermin: ") {
-                stripped
-            } else {
-                signup_information_messy
-            }
-            .to_string();
+// expproc (signup) and cancelproc (registered exams) render the same tree table,
+// they only differ in the table id, the deadline wording and the info text
+struct ExamTableConfig {
+    table_sel: &'static Selector,
+    deadline_re: &'static Regex,
+    clean_info: fn(&str) -> String,
+}
 
-        let exam_date = main_subline_texts.next().map(|el| el.to_string());
-        let exam_time = main_subline_texts.nth(1).map(|el| el.to_string());
-        let exam_room = main_subline_texts
-            .next()
-            .map(|el| el.strip_prefix(", ").unwrap_or(el).to_string());
+// common columns of both exam tables
+struct ExamTableRow {
+    name: String,
+    verfahren: String,
+    pruefart: String,
+    status_kind: ExamStatus,
+    signup_information: String,
+    exam_date: Option<String>,
+    exam_time: Option<String>,
+    exam_room: Option<String>,
+    warning_message: Option<String>,
+    deadline: Option<String>,
+    internal_metadata: Option<ExamRegistrationMetadata>,
+}
 
-        let warning_message = sublines.next().map(|second_subline| {
-            second_subline
-                .text()
-                .fold(String::new(), |a, b| a + b)
-                .trim_start()
-                // campusdual html is fucked in case of no time, so replace that but leave 1 space
-                .replace("   :  ", "")
-                .to_string()
-        });
+impl ExamTableRow {
+    fn exam_date_parsed(&self) -> Option<NaiveDate> {
+        self.exam_date.as_deref().and_then(parse_cd_date)
+    }
+
+    // exam_time is "08:00 - 09:30" or only the start time
+    fn exam_times(&self) -> (Option<NaiveTime>, Option<NaiveTime>) {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"bis (\d{2}\.\d{2}\.\d{4})").unwrap();
+            static ref TIME_RE: Regex = Regex::new(r"(\d{1,2}):(\d{2})").unwrap();
         }
-        let signup_until = warning_message.as_ref().and_then(|msg| {
-            RE.captures(msg)
-                .and_then(|caps| caps.get(1).map(|m| m.as_str().to_string()))
-        });
 
-        signup_options.push(CampusDualSignupOption {
-            name,
-            verfahren,
-            pruefart,
-            status,
-            signup_information,
-            exam_date,
-            exam_time,
-            exam_room,
-            warning_message,
-            signup_until,
-            internal_metadata,
-        });
+        let mut times = TIME_RE
+            .captures_iter(self.exam_time.as_deref().unwrap_or_default())
+            .filter_map(|caps| {
+                NaiveTime::from_hms_opt(caps[1].parse().ok()?, caps[2].parse().ok()?, 0)
+            });
+        let start = times.next();
+        let end = times
+            .next()
+            .filter(|end| start.is_some_and(|start| *end > start));
+        (start, end)
     }
+}
 
-    Ok(signup_options)
+pub async fn extract_exam_signup_options(
+    html_text: String,
+) -> Result<ExamTableExtraction<CampusDualSignupOption>> {
+    lazy_static! {
+        static ref TABLE_SEL: Selector = Selector::parse("#expproc tbody").unwrap();
+        static ref RE: Regex = Regex::new(r"bis (\d{2}\.\d{2}\.\d{4})").unwrap();
+    };
+
+    let config = ExamTableConfig {
+        table_sel: &TABLE_SEL,
+        deadline_re: &RE,
+        clean_info: |info| {
+            info.strip_suffix(", Prüfungstermin: ")
+                .unwrap_or(info)
+                .to_string()
+        },
+    };
+
+    let (rows, warnings) = extract_exam_table(&html_text, &config);
+    let options = rows
+        .into_iter()
+        .map(|row| {
+            let (exam_start, exam_end) = row.exam_times();
+            CampusDualSignupOption {
+                status: row.status_kind.emoji().to_string(),
+                status_label: row.status_kind.label(),
+                exam_date_parsed: row.exam_date_parsed(),
+                exam_start,
+                exam_end,
+                exam_room_parsed: row.exam_room.as_deref().and_then(ExamRoom::parse),
+                signup_until_date: row.deadline.as_deref().and_then(parse_cd_date),
+                name: row.name,
+                verfahren: row.verfahren,
+                pruefart: row.pruefart,
+                status_kind: row.status_kind,
+                signup_information: row.signup_information,
+                exam_date: row.exam_date,
+                exam_time: row.exam_time,
+                exam_room: row.exam_room,
+                warning_message: row.warning_message,
+                signup_until: row.deadline,
+                internal_metadata: row.internal_metadata,
+            }
+        })
+        .collect();
+
+    Ok(ExamTableExtraction { options, warnings })
 }
 
 pub async fn extract_exam_verfahren_options(
     html_text: String,
-) -> Result<Vec<CampusDualVerfahrenOption>> {
+) -> Result<ExamTableExtraction<CampusDualVerfahrenOption>> {
     lazy_static! {
-        static ref IMG_SEL: Selector = Selector::parse("img").unwrap();
         static ref TABLE_SEL: Selector = Selector::parse("#exopen tbody").unwrap();
+        static ref RE: Regex = Regex::new(r"bis zum (\d{2}\.\d{2}\.\d{4})").unwrap();
+    };
+
+    let config = ExamTableConfig {
+        table_sel: &TABLE_SEL,
+        deadline_re: &RE,
+        clean_info: |info| match info.split_once("Prüfungstermin") {
+            Some((stripped, _)) => stripped.replace(", ", ""),
+            None => info.to_string(),
+        },
+    };
+
+    let (rows, warnings) = extract_exam_table(&html_text, &config);
+    let options = rows
+        .into_iter()
+        .map(|row| {
+            let (exam_start, exam_end) = row.exam_times();
+            CampusDualVerfahrenOption {
+                status: row.status_kind.emoji().to_string(),
+                status_label: row.status_kind.label(),
+                exam_date_parsed: row.exam_date_parsed(),
+                exam_start,
+                exam_end,
+                exam_room_parsed: row.exam_room.as_deref().and_then(ExamRoom::parse),
+                signoff_until_date: row.deadline.as_deref().and_then(parse_cd_date),
+                name: row.name,
+                verfahren: row.verfahren,
+                pruefart: row.pruefart,
+                status_kind: row.status_kind,
+                signup_information: row.signup_information,
+                exam_date: row.exam_date,
+                exam_time: row.exam_time,
+                exam_room: row.exam_room,
+                warning_message: row.warning_message,
+                signoff_until: row.deadline,
+                internal_metadata: row.internal_metadata,
+            }
+        })
+        .collect();
+
+    Ok(ExamTableExtraction { options, warnings })
+}
+
+// broken rows are skipped and a missing table is reported, both with a warning
+fn extract_exam_table(
+    html_text: &str,
+    config: &ExamTableConfig,
+) -> (Vec<ExamTableRow>, Vec<ParserWarning>) {
+    lazy_static! {
+        static ref IMG_SEL: Selector = Selector::parse("img").unwrap();
         static ref NORMAL_LINE_SEL: Selector = Selector::parse(".child-of-node-0").unwrap();
         static ref TD_SEL: Selector = Selector::parse("td").unwrap();
         static ref METADATA_SEL: Selector = Selector::parse("td>a.booking").unwrap();
     };
 
-    let mut signup_options = Vec::new();
+    let mut rows = Vec::new();
+    let mut warnings = Vec::new();
+
+    let document = Html::parse_document(html_text);
+    // a missing table (expired session, changed markup) must not look like "no exams"
+    let Some(table) = document.select(config.table_sel).next() else {
+        warnings.push(ParserWarning::new(
+            None,
+            "table",
+            "exam table not found (expired session or changed page)",
+        ));
+        return (rows, warnings);
+    };
+
+    for (index, line) in table.select(&NORMAL_LINE_SEL).enumerate() {
+        let Some(l_id) = line.value().attr("id") else {
+            warnings.push(ParserWarning::new(
+                None,
+                "id",
+                &format!("row {index} has no id, skipped"),
+            ));
+            continue;
+        };
 
-    let document = Html::parse_document(&html_text);
-    let table = document.select(&TABLE_SEL).next().unwrap();
-    let top_level_lines = table.select(&NORMAL_LINE_SEL);
-    for line in top_level_lines {
-        let l_id = line.value().attr("id").unwrap();
         let mut content = line.select(&TD_SEL);
+        let mut next_cell = |field: &str| {
+            let text = content.next().and_then(first_text);
+            if text.is_none() {
+                warnings.push(ParserWarning::new(
+                    Some(l_id),
+                    field,
+                    "column missing or empty",
+                ));
+            }
+            text.unwrap_or_default()
+        };
+        let name = next_cell("name");
+        let verfahren = next_cell("verfahren");
+        let pruefart = next_cell("pruefart");
+
+        let Ok(subline_selector) = Selector::parse(&format!(".child-of-{l_id}")) else {
+            warnings.push(ParserWarning::new(
+                Some(l_id),
+                "id",
+                "row id is not usable as a selector, skipped",
+            ));
+            continue;
+        };
+        let mut sublines = table.select(&subline_selector);
+        let Some(main_subline) = sublines.next() else {
+            warnings.push(ParserWarning::new(
+                Some(l_id),
+                "details",
+                "row has no detail line, skipped",
+            ));
+            continue;
+        };
 
-        let name = content.next().unwrap().text().next().unwrap().to_string();
-        let verfahren = content.next().unwrap().text().next().unwrap().to_string();
-        let pruefart = content.next().unwrap().text().next().unwrap().to_string();
-
-        let subline_selector = &Selector::parse(&format!(".child-of-{l_id}")).unwrap();
-        let mut sublines = table.select(subline_selector);
-        let main_subline = sublines.next().unwrap();
-
-        let internal_metadata =
-            main_subline
-                .select(&METADATA_SEL)
-                .next()
-                .map(|meta_el| ExamRegistrationMetadata {
-                    assessment: meta_el.value().attr("data-evob_objid").unwrap().to_string(),
-                    peryr: meta_el.value().attr("data-peryr").unwrap().to_string(),
-                    perid: meta_el.value().attr("data-perid").unwrap().to_string(),
-                    offerno: meta_el.value().attr("data-offerno").unwrap().to_string(),
-                });
+        let internal_metadata = main_subline
+            .select(&METADATA_SEL)
+            .next()
+            .and_then(|meta_el| {
+                let attr = |name: &str| meta_el.value().attr(name).map(|value| value.to_string());
+                let metadata = (|| {
+                    Some(ExamRegistrationMetadata {
+                        assessment: attr("data-evob_objid")?,
+                        peryr: attr("data-peryr")?,
+                        perid: attr("data-perid")?,
+                        offerno: attr("data-offerno")?,
+                    })
+                })();
+                if metadata.is_none() {
+                    warnings.push(ParserWarning::new(
+                        Some(l_id),
+                        "internal_metadata",
+                        "booking link is missing data attributes",
+                    ));
+                }
+                metadata
+            });
 
         let status_icon_url = main_subline
             .select(&IMG_SEL)
             .next()
-            .unwrap()
-            .value()
-            .attr("src")
-            .unwrap();
-        let status = match status_icon_url {
-            "/images/missed.png" => "🚫",
-            "/images/yellow.png" => "📝",
-            "/images/exclamation.jpg" => "⚠️",
-            _ => "⁉️",
+            .and_then(|img| img.value().attr("src"));
+        if status_icon_url.is_none() {
+            warnings.push(ParserWarning::new(
+                Some(l_id),
+                "status",
+                "no status icon, status unknown",
+            ));
         }
-        .to_string();
+        let status_kind = ExamStatus::from_icon(status_icon_url.unwrap_or_default());
 
         // my shoddy code demands that the iterator is over owned values and not references,
         // else the iterator doesn't consume the values and causes wrapping after the first None
         let mut main_subline_texts = main_subline.text().collect::<Vec<_>>().into_iter();
 
-        if main_subline_texts.len() == 0 {
-            signup_options.push(CampusDualVerfahrenOption {
+        let Some(signup_information_messy) = main_subline_texts.next() else {
+            warnings.push(ParserWarning::new(
+                Some(l_id),
+                "signup_information",
+                "detail line is empty",
+            ));
+            rows.push(ExamTableRow {
                 name,
                 verfahren,
                 pruefart,
-                status,
+                status_kind,
                 signup_information: "Daten konnten nicht extrahiert werden".to_string(),
                 exam_date: None,
                 exam_time: None,
                 exam_room: None,
                 warning_message: None,
-                signoff_until: None,
+                deadline: None,
                 internal_metadata: None,
             });
-
             continue;
-        }
-
-        let signup_information_messy = main_subline_texts.next().unwrap().trim_start();
-        let signup_information = if let Some(stripped) = signup_information_messy
-            .split_once("Prüfungstermin")
-            .map(|split| split.0.replace(", ", ""))
-        {
-            stripped
-        } else {
-            signup_information_messy.to_string()
         };
+        let signup_information = (config.clean_info)(signup_information_messy.trim_start());
 
         let exam_date = main_subline_texts.next().map(|el| el.to_string());
         let exam_time = main_subline_texts.nth(1).map(|el| el.to_string());
@@ -457,28 +602,53 @@ pub async fn extract_exam_verfahren_options(
                 .replace("   :  ", "")
                 .to_string()
         });
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"bis zum (\d{2}\.\d{2}\.\d{4})").unwrap();
-        }
-        let signoff_until = warning_message.as_ref().and_then(|msg| {
-            RE.captures(msg)
+        let deadline = warning_message.as_ref().and_then(|msg| {
+            config
+                .deadline_re
+                .captures(msg)
                 .and_then(|caps| caps.get(1).map(|m| m.as_str().to_string()))
         });
 
-        signup_options.push(CampusDualVerfahrenOption {
+        let row = ExamTableRow {
             name,
             verfahren,
             pruefart,
-            status,
+            status_kind,
             signup_information,
             exam_date,
             exam_time,
             exam_room,
             warning_message,
-            signoff_until,
+            deadline,
             internal_metadata,
-        });
+        };
+
+        if row.exam_date.is_some() && row.exam_date_parsed().is_none() {
+            warnings.push(ParserWarning::new(
+                Some(l_id),
+                "exam_date",
+                "not a dd.mm.yyyy date",
+            ));
+        }
+        if row
+            .exam_time
+            .as_deref()
+            .is_some_and(|time| !time.trim().is_empty())
+            && row.exam_times().0.is_none()
+        {
+            warnings.push(ParserWarning::new(
+                Some(l_id),
+                "exam_time",
+                "no HH:MM time found",
+            ));
+        }
+
+        rows.push(row);
+    }
+
+    for warning in &warnings {
+        log::warn!("CD exam table parser: {warning:?}");
     }
 
-    Ok(signup_options)
+    (rows, warnings)
 }
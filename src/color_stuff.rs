@@ -1,13 +1,203 @@
-fn hex_to_rgb(hex: &str) -> (f32, f32, f32) {
-    let hex = hex.trim_start_matches('#');
-    let r = u8::from_str_radix(&hex[0..2], 16).expect("Invalid hex color") as f32;
-    let g = u8::from_str_radix(&hex[2..4], 16).expect("Invalid hex color") as f32;
-    let b = u8::from_str_radix(&hex[4..6], 16).expect("Invalid hex color") as f32;
-    (r, g, b)
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use fnv::FnvHasher;
+use http::StatusCode;
+
+use crate::types::{ColorPalette, ResponseError, StundenplanQuery};
+
+// qualitative palettes without reds, red is reserved for the events CampusDual marks "darkred"
+// (exams, short notice changes)
+const BRIGHT_PALETTE: [&str; 12] = [
+    "#4E79A7", "#F28E2B", "#59A14F", "#B07AA1", "#76B7B2", "#EDC948", "#FF9DA7", "#9C755F",
+    "#BAB0AC", "#A0CBE8", "#8CD17D", "#D4A6C8",
+];
+// Okabe-Ito, extended with Paul Tol's muted scheme
+const COLORBLIND_SAFE_PALETTE: [&str; 12] = [
+    "#E69F00", "#56B4E9", "#009E73", "#F0E442", "#0072B2", "#CC79A7", "#999999", "#332288",
+    "#117733", "#44AA99", "#88CCEE", "#DDCC77",
+];
+// dark, desaturated tones that don't glare on a dark background
+const DARK_PALETTE: [&str; 12] = [
+    "#1B4F72", "#145A32", "#7E5109", "#4A235A", "#0B5345", "#424949", "#6E2C00", "#1A5276",
+    "#4D5656", "#512E5F", "#196F3D", "#784212",
+];
+
+impl ColorPalette {
+    fn colors(self) -> &'static [&'static str] {
+        match self {
+            ColorPalette::Classic => &[],
+            ColorPalette::Bright => &BRIGHT_PALETTE,
+            ColorPalette::ColorblindSafe => &COLORBLIND_SAFE_PALETTE,
+            ColorPalette::Dark => &DARK_PALETTE,
+        }
+    }
+
+    fn highlight_color(self) -> &'static str {
+        match self {
+            ColorPalette::Classic => "#D41610",
+            ColorPalette::Bright => "#E15759",
+            ColorPalette::ColorblindSafe => "#D55E00",
+            ColorPalette::Dark => "#E06666",
+        }
+    }
+}
+
+// palette plus the user's own colors, keyed by module code or title
+#[derive(Debug, Default)]
+pub struct ColorScheme {
+    pub palette: ColorPalette,
+    pub overrides: HashMap<String, String>,
+}
+
+impl ColorScheme {
+    pub fn from_query(query: &StundenplanQuery) -> Result<Self, ResponseError> {
+        Ok(ColorScheme {
+            palette: query.palette,
+            overrides: match &query.colors {
+                Some(colors) => parse_color_overrides(colors)?,
+                None => HashMap::new(),
+            },
+        })
+    }
+
+    // modules are assigned in sorted order, so the same timetable always gets the same colors
+    pub fn assign<'a>(&self, module_keys: impl IntoIterator<Item = &'a str>) -> TimetableColors {
+        let palette = self.palette.colors();
+        let mut module_keys = module_keys.into_iter().collect::<Vec<_>>();
+        module_keys.sort_unstable();
+        module_keys.dedup();
+
+        // the classic scheme has no palette, its colors come from the title alone
+        let mut assigned = HashMap::new();
+        let mut used = HashSet::new();
+        for key in module_keys.into_iter().filter(|_| !palette.is_empty()) {
+            // more modules than colors: start reusing them
+            if used.len() == palette.len() {
+                used.clear();
+            }
+            let start = (string_hash(key) % palette.len() as u64) as usize;
+            let index = (0..palette.len())
+                .map(|offset| (start + offset) % palette.len())
+                .find(|index| !used.contains(index))
+                .unwrap_or(start);
+            used.insert(index);
+            assigned.insert(key.to_string(), palette[index].to_string());
+        }
+
+        TimetableColors {
+            highlight_color: self.palette.highlight_color(),
+            assigned,
+            overrides: self.overrides.clone(),
+        }
+    }
+}
+
+pub struct TimetableColors {
+    highlight_color: &'static str,
+    assigned: HashMap<String, String>,
+    overrides: HashMap<String, String>,
+}
+
+impl TimetableColors {
+    // darkred events stay highlighted, then the user's colors (module code before title), then the palette
+    pub fn event_colors(&self, cd_color: &str, module_key: &str, title: &str) -> (String, String) {
+        let color = match cd_color {
+            "darkred" => self.highlight_color.to_string(),
+            _ => self
+                .overrides
+                .get(module_key)
+                .or_else(|| self.overrides.get(title.trim()))
+                .or_else(|| self.assigned.get(module_key))
+                .cloned()
+                .unwrap_or_else(|| string_to_rgb(&format!("0{title}0"))),
+        };
+        let font_color = font_color_for(&color).to_string();
+
+        (color, font_color)
+    }
+}
+
+// "5CS-MA1-10:#1B4F72,Sport:FFAA00", the "#" is optional since it has to be escaped in URLs
+pub fn parse_color_overrides(raw: &str) -> Result<HashMap<String, String>, ResponseError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (key, color) = entry
+                .rsplit_once(':')
+                .map(|(key, color)| {
+                    (
+                        key.trim(),
+                        format!("#{}", color.trim().trim_start_matches('#')),
+                    )
+                })
+                .filter(|(key, color)| !key.is_empty() && hex_to_rgb(color).is_some())
+                .ok_or(ResponseError {
+                    message: format!("Ungültige Farbzuordnung: {entry}"),
+                    status_code: StatusCode::BAD_REQUEST,
+                })?;
+            Ok((key.to_string(), color.to_uppercase()))
+        })
+        .collect()
 }
 
-pub fn hex_to_luminance(hex: &str) -> f32 {
-    let (r, g, b) = hex_to_rgb(hex);
+fn hex_to_rgb(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+    Some((r, g, b))
+}
+
+// WCAG 2.x relative luminance (0.0 black to 1.0 white)
+fn relative_luminance(hex: &str) -> Option<f64> {
+    let (r, g, b) = hex_to_rgb(hex)?;
+    let linear = |channel: u8| {
+        let channel = channel as f64 / 255.0;
+        if channel <= 0.03928 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    Some(0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b))
+}
+
+// whichever of black and white has the higher WCAG contrast ratio
+pub fn font_color_for(hex: &str) -> &'static str {
+    let Some(luminance) = relative_luminance(hex) else {
+        return "#000000";
+    };
+    let white_contrast = 1.05 / (luminance + 0.05);
+    let black_contrast = (luminance + 0.05) / 0.05;
+
+    if white_contrast > black_contrast {
+        "#FFFFFF"
+    } else {
+        "#000000"
+    }
+}
+
+fn string_hash(input: &str) -> u64 {
+    let mut hasher = FnvHasher::default();
+    input.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn string_to_rgb(input: &str) -> String {
+    let hash = string_hash(input);
+
+    // Extract RGB components from the hash
+    let r = (hash & 0xFF) as u8;
+    let g = ((hash >> 8) & 0xFF) as u8;
+    let b = ((hash >> 16) & 0xFF) as u8;
 
-    0.299 * r + 0.587 * g + 0.114 * b
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
 }
@@ -5,6 +5,10 @@ use reqwest::Certificate;
 
 use crate::encryption::{get_aes_from_env, get_jwt_keys_from_env};
 
+pub const PARSER_WARNINGS_HEADER: &str = "x-parser-warnings";
+pub const PARSER_WARNING_COUNT_HEADER: &str = "x-parser-warning-count";
+pub const BATCH_TOTAL_HEADER: &str = "x-batch-total";
+
 pub static JWT_ENC_KEY: OnceLock<EncodingKey> = OnceLock::new();
 pub static JWT_DEC_KEY: OnceLock<DecodingKey> = OnceLock::new();
 pub static AES_KEY: OnceLock<[u8; 32]> = OnceLock::new();
@@ -13,6 +17,18 @@ pub static RATELIMIT_QUOTA: OnceLock<u32> = OnceLock::new();
 pub static RATELIMIT_RESTORE_INTERVAL_SEC: OnceLock<u64> = OnceLock::new();
 pub static LOGIN_RATELIMIT_QUOTA: OnceLock<u32> = OnceLock::new();
 pub static LOGIN_RATELIMIT_RESTORE_INTERVAL_SEC: OnceLock<u64> = OnceLock::new();
+pub static BATCH_CONCURRENCY: OnceLock<usize> = OnceLock::new();
+pub static EXAM_SCHEDULER_FILE: OnceLock<Option<String>> = OnceLock::new();
+pub static EXAM_CACHE_TTL_SEC: OnceLock<u64> = OnceLock::new();
+pub static EXAM_CACHE_MAX_ENTRIES: OnceLock<usize> = OnceLock::new();
+pub static TIMETABLE_WATCH_FILE: OnceLock<Option<String>> = OnceLock::new();
+pub static TIMETABLE_WATCH_INTERVAL_SEC: OnceLock<u64> = OnceLock::new();
+pub static TIMETABLE_EXEMPTIONS_FILE: OnceLock<Option<String>> = OnceLock::new();
+pub static SMTP_URL: OnceLock<Option<String>> = OnceLock::new();
+pub static SMTP_FROM: OnceLock<String> = OnceLock::new();
+pub static GROUP_TIMETABLE_CACHE_TTL_SEC: OnceLock<u64> = OnceLock::new();
+pub static GROUP_TIMETABLE_CACHE_MAX_ENTRIES: OnceLock<usize> = OnceLock::new();
+pub static ROOM_DIRECTORY_FILE: OnceLock<Option<String>> = OnceLock::new();
 
 pub fn set_statics_from_env() {
     AES_KEY.set(get_aes_from_env()).unwrap();
@@ -52,4 +68,62 @@ pub fn set_statics_from_env() {
                 .unwrap_or(10),
         )
         .unwrap();
+    BATCH_CONCURRENCY
+        .set(
+            env::var("BATCH_CONCURRENCY")
+                .and_then(|key| key.parse().map_err(|_| env::VarError::NotPresent))
+                .unwrap_or(4),
+        )
+        .unwrap();
+    EXAM_SCHEDULER_FILE
+        .set(env::var("EXAM_SCHEDULER_FILE").ok())
+        .unwrap();
+    EXAM_CACHE_TTL_SEC
+        .set(
+            env::var("EXAM_CACHE_TTL_SEC")
+                .and_then(|key| key.parse().map_err(|_| env::VarError::NotPresent))
+                .unwrap_or(6 * 60 * 60),
+        )
+        .unwrap();
+    EXAM_CACHE_MAX_ENTRIES
+        .set(
+            env::var("EXAM_CACHE_MAX_ENTRIES")
+                .and_then(|key| key.parse().map_err(|_| env::VarError::NotPresent))
+                .unwrap_or(2000),
+        )
+        .unwrap();
+    TIMETABLE_WATCH_FILE
+        .set(env::var("TIMETABLE_WATCH_FILE").ok())
+        .unwrap();
+    TIMETABLE_WATCH_INTERVAL_SEC
+        .set(
+            env::var("TIMETABLE_WATCH_INTERVAL_SEC")
+                .and_then(|key| key.parse().map_err(|_| env::VarError::NotPresent))
+                .unwrap_or(30 * 60),
+        )
+        .unwrap();
+    TIMETABLE_EXEMPTIONS_FILE
+        .set(env::var("TIMETABLE_EXEMPTIONS_FILE").ok())
+        .unwrap();
+    SMTP_URL.set(env::var("SMTP_URL").ok()).unwrap();
+    SMTP_FROM
+        .set(env::var("SMTP_FROM").unwrap_or("campus-api <noreply@localhost>".to_string()))
+        .unwrap();
+    GROUP_TIMETABLE_CACHE_TTL_SEC
+        .set(
+            env::var("GROUP_TIMETABLE_CACHE_TTL_SEC")
+                .and_then(|key| key.parse().map_err(|_| env::VarError::NotPresent))
+                .unwrap_or(0),
+        )
+        .unwrap();
+    GROUP_TIMETABLE_CACHE_MAX_ENTRIES
+        .set(
+            env::var("GROUP_TIMETABLE_CACHE_MAX_ENTRIES")
+                .and_then(|key| key.parse().map_err(|_| env::VarError::NotPresent))
+                .unwrap_or(1000),
+        )
+        .unwrap();
+    ROOM_DIRECTORY_FILE
+        .set(env::var("ROOM_DIRECTORY_FILE").ok())
+        .unwrap();
 }
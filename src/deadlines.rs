@@ -0,0 +1,196 @@
+use axum::{extract::Query, Extension, Json};
+use chrono::{DateTime, Duration, NaiveTime, TimeZone};
+use chrono_tz::{Europe::Berlin, Tz};
+
+use crate::{
+    campus_backend::req_client_funcs::{get_client_default, get_client_with_cd_cookie},
+    exam_calendar::{exam_events_for_listings, fetch_exam_listings, ExamListing},
+    services::{fetch_reminders, fetch_timeline},
+    time_stuff::{berlin_now, parse_sap_date, parse_sap_time},
+    types::{
+        CampusTimelineEvent, CdAuthData, Deadline, DeadlineKind, DeadlineSource, DeadlinesQuery,
+        ExamCalendarEvent, ExamCalendarEventKind, ResponseError, UpcomingReminder,
+    },
+};
+
+pub async fn get_deadlines(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Query(deadlines_query): Query<DeadlinesQuery>,
+) -> Result<Json<Vec<Deadline>>, ResponseError> {
+    let cookie_client = get_client_with_cd_cookie(true, cd_auth_data.cookie.clone())?;
+    let client = get_client_default(true)?;
+
+    let listings = fetch_exam_listings(&cookie_client).await?;
+    let (exam_events, reminders, timeline) = tokio::join!(
+        exam_events_for_listings(listings.clone(), &cd_auth_data),
+        fetch_reminders(&client, &cd_auth_data),
+        fetch_timeline(&client, &cd_auth_data),
+    );
+    let exam_events = exam_events?;
+
+    let mut deadlines = exam_events.iter().map(exam_deadline).collect::<Vec<_>>();
+    deadlines.extend(table_deadlines(&listings, &exam_events));
+
+    // the dashboard sources are a nice to have, the exams are what matters
+    match reminders {
+        Ok(reminders) => deadlines.extend(reminders.upcoming.iter().filter_map(reminder_deadline)),
+        Err(e) => log::warn!("Deadlines: reminders unavailable: {}", e.message),
+    }
+    match timeline {
+        Ok(Some(timeline)) => deadlines.extend(timeline.events.iter().flat_map(timeline_deadlines)),
+        Ok(None) => log::warn!("Deadlines: timeline could not be parsed"),
+        Err(e) => log::warn!("Deadlines: timeline unavailable: {}", e.message),
+    }
+
+    let now = berlin_now();
+    let horizon = deadlines_query.days.map(|days| now + Duration::days(days));
+    deadlines.retain(|deadline| {
+        (deadlines_query.include_past || deadline.due >= now)
+            && horizon.is_none_or(|horizon| deadline.due <= horizon)
+    });
+    deadlines.sort_by(|a, b| a.due.cmp(&b.due).then_with(|| a.title.cmp(&b.title)));
+
+    Ok(Json(deadlines))
+}
+
+fn exam_deadline(event: &ExamCalendarEvent) -> Deadline {
+    let (source, kind) = match event.kind {
+        ExamCalendarEventKind::Exam if event.registered => {
+            (DeadlineSource::ExamCancel, DeadlineKind::Exam)
+        }
+        ExamCalendarEventKind::Exam => (DeadlineSource::ExamSignup, DeadlineKind::Exam),
+        ExamCalendarEventKind::RegistrationBegin => {
+            (DeadlineSource::ExamDetails, DeadlineKind::RegistrationBegin)
+        }
+        ExamCalendarEventKind::RegistrationEnd => {
+            (DeadlineSource::ExamDetails, DeadlineKind::RegistrationEnd)
+        }
+        ExamCalendarEventKind::DeregistrationEnd => {
+            (DeadlineSource::ExamDetails, DeadlineKind::DeregistrationEnd)
+        }
+    };
+
+    Deadline {
+        source,
+        kind,
+        title: event.title.clone(),
+        due: event.start,
+        assessment: event.internal_metadata.clone(),
+    }
+}
+
+// signup_until / signoff_until from the tables, only if the offer details didn't provide one
+fn table_deadlines(listings: &[ExamListing], exam_events: &[ExamCalendarEvent]) -> Vec<Deadline> {
+    listings
+        .iter()
+        .filter_map(|listing| {
+            let (source, kind, calendar_kind, label) = if listing.registered {
+                (
+                    DeadlineSource::ExamCancel,
+                    DeadlineKind::DeregistrationEnd,
+                    ExamCalendarEventKind::DeregistrationEnd,
+                    "Abmeldeschluss",
+                )
+            } else {
+                (
+                    DeadlineSource::ExamSignup,
+                    DeadlineKind::RegistrationEnd,
+                    ExamCalendarEventKind::RegistrationEnd,
+                    "Anmeldeschluss",
+                )
+            };
+
+            let known = exam_events.iter().any(|event| {
+                event.kind == calendar_kind
+                    && event.exam_name == listing.name
+                    && event.internal_metadata == listing.metadata
+            });
+            if known {
+                return None;
+            }
+
+            // "bis 12.03.2024" includes that day
+            let due = Berlin
+                .from_local_datetime(
+                    &listing
+                        .table_deadline?
+                        .and_time(NaiveTime::from_hms_opt(23, 59, 0)?),
+                )
+                .earliest()?;
+
+            Some(Deadline {
+                source,
+                kind,
+                title: format!("{label}: {}", listing.name),
+                due,
+                assessment: listing.metadata.clone(),
+            })
+        })
+        .collect()
+}
+
+fn reminder_deadline(reminder: &UpcomingReminder) -> Option<Deadline> {
+    let date = parse_sap_date(&reminder.evdat)?;
+    let time = parse_sap_time(&reminder.beguz).unwrap_or(NaiveTime::MIN);
+    let due = Berlin
+        .from_local_datetime(&date.and_time(time))
+        .earliest()?;
+
+    let title = [&reminder.sm_stext, &reminder.sm_short, &reminder.comment]
+        .into_iter()
+        .find(|text| !text.trim().is_empty())?
+        .trim()
+        .to_string();
+
+    Some(Deadline {
+        source: DeadlineSource::Reminders,
+        kind: DeadlineKind::Appointment,
+        title,
+        due,
+        assessment: None,
+    })
+}
+
+// same colors get_timeline uses to tell the semester kinds apart
+fn timeline_deadlines(event: &CampusTimelineEvent) -> Vec<Deadline> {
+    let parse = |raw: &str| -> Option<DateTime<Tz>> {
+        DateTime::parse_from_str(raw, "%a, %d %b %Y %H:%M:%S %z")
+            .ok()
+            .map(|date_time| date_time.with_timezone(&Berlin))
+    };
+    let deadline = |kind: DeadlineKind, title: String, due: Option<DateTime<Tz>>| {
+        due.map(|due| Deadline {
+            source: DeadlineSource::Timeline,
+            kind,
+            title,
+            due,
+            assessment: None,
+        })
+    };
+
+    match event.color.as_str() {
+        "#fcbe04" | "#0070a3" | "#119911" => [
+            deadline(
+                DeadlineKind::SemesterStart,
+                format!("Beginn: {}", event.title),
+                parse(&event.start),
+            ),
+            deadline(
+                DeadlineKind::SemesterEnd,
+                format!("Ende: {}", event.title),
+                parse(&event.end),
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+        "#880000" => deadline(
+            DeadlineKind::Special,
+            event.title.clone(),
+            parse(&event.start),
+        )
+        .into_iter()
+        .collect(),
+        _ => Vec::new(),
+    }
+}
@@ -7,7 +7,10 @@ use jsonwebtoken::{DecodingKey, EncodingKey};
 use rand::Rng;
 use std::{env, str};
 
-use crate::constants::AES_KEY;
+use crate::{
+    constants::AES_KEY,
+    types::{CampusLoginData, CdAuthData, ResponseError},
+};
 
 fn generate_nonce() -> [u8; 12] {
     let mut nonce = [0u8; 12];
@@ -37,6 +40,10 @@ pub fn decrypt(nonce: &str, ciphertext: &str) -> Result<String> {
 
     let nonce = BASE64_STANDARD.decode(nonce)?;
     let ciphertext = BASE64_STANDARD.decode(ciphertext)?;
+    // Nonce::from_slice panics on any other length, and the nonce may come from a client
+    if nonce.len() != 12 {
+        return Err(anyhow!("invalid nonce length"));
+    }
 
     let plaintext = cipher
         .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
@@ -45,6 +52,25 @@ pub fn decrypt(nonce: &str, ciphertext: &str) -> Result<String> {
     Ok(String::from_utf8(plaintext)?)
 }
 
+// for background jobs that have to log in to CampusDual by themselves
+pub fn encrypt_login_data(cd_auth_data: &CdAuthData) -> Result<(String, String), ResponseError> {
+    let login_data = serde_json::to_string(&CampusLoginData {
+        username: cd_auth_data.user.clone(),
+        password: cd_auth_data.password.clone(),
+    })?;
+
+    encrypt(&login_data).map_err(|status_code| ResponseError {
+        message: "Internal Server Error".to_string(),
+        status_code,
+    })
+}
+
+pub fn decrypt_login_data(nonce: &str, ciphertext: &str) -> Option<CampusLoginData> {
+    decrypt(nonce, ciphertext)
+        .ok()
+        .and_then(|plain| serde_json::from_str(&plain).ok())
+}
+
 pub fn get_aes_from_env() -> [u8; 32] {
     let key = env::var("AES_KEY").expect("AES_KEY environment variable not set");
 
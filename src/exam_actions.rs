@@ -0,0 +1,635 @@
+use std::time::Duration;
+
+use axum::{body::Body, http::Response, response::IntoResponse, Json};
+use chrono::NaiveDate;
+use http::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    campus_backend::req_client_funcs::{
+        extract_exam_signup_options, extract_exam_verfahren_options, get_client_default,
+        get_client_with_cd_cookie,
+    },
+    services::check_batch_len,
+    time_stuff::berlin_now,
+    types::{
+        CampusDualSignupOption, CampusDualVerfahrenOption, CdAuthData, CdExamDetails,
+        ExamRegistrationMetadata, ExamStatus, ExamTableExtraction, ResponseError,
+    },
+};
+
+// pause between two writes of a bulk request
+const BULK_WRITE_DELAY: Duration = Duration::from_millis(1500);
+// with the pause above, a full bulk request takes about half a minute
+const MAX_BULK_EXAMS: usize = 20;
+
+// Why a registration/cancellation was not forwarded to CampusDual
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ExamActionRejection {
+    // the assessment is not (or no longer) in the list the action is offered in
+    NotOffered,
+    // the assessment exists, but with a different offer/period (stale UI state)
+    OfferMismatch { current: ExamRegistrationMetadata },
+    DeadlinePassed { deadline: NaiveDate },
+    StatusForbids { status: ExamStatus },
+}
+
+impl ExamActionRejection {
+    fn message(&self) -> String {
+        match self {
+            ExamActionRejection::NotOffered => {
+                "Die Prüfung wird für diese Aktion nicht (mehr) angeboten".to_string()
+            }
+            ExamActionRejection::OfferMismatch { .. } => {
+                "Die Prüfungsdaten sind veraltet, bitte neu laden".to_string()
+            }
+            ExamActionRejection::DeadlinePassed { deadline } => {
+                format!(
+                    "Die Frist ist am {} abgelaufen",
+                    deadline.format("%d.%m.%Y")
+                )
+            }
+            ExamActionRejection::StatusForbids { status } => {
+                format!(
+                    "Der Prüfungsstatus ({}) erlaubt diese Aktion nicht",
+                    status.label().de
+                )
+            }
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ExamActionRejection::NotOffered => StatusCode::NOT_FOUND,
+            _ => StatusCode::CONFLICT,
+        }
+    }
+}
+
+// What CampusDual made of a registration/cancellation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExamActionOutcome {
+    Registered,
+    AlreadyRegistered,
+    DeadlinePassed,
+    NotEligible,
+    Cancelled,
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExamActionResult {
+    pub outcome: ExamActionOutcome,
+    // whether re-reading the exam lists confirmed the outcome, None if that was not possible
+    pub verified: Option<bool>,
+    pub raw_response: String,
+}
+
+impl ExamActionResult {
+    pub fn status_code(&self) -> StatusCode {
+        match self.outcome {
+            ExamActionOutcome::Registered | ExamActionOutcome::Cancelled => {
+                if self.verified == Some(false) {
+                    StatusCode::BAD_GATEWAY
+                } else {
+                    StatusCode::OK
+                }
+            }
+            ExamActionOutcome::AlreadyRegistered | ExamActionOutcome::DeadlinePassed => {
+                StatusCode::CONFLICT
+            }
+            ExamActionOutcome::NotEligible => StatusCode::FORBIDDEN,
+            ExamActionOutcome::Unknown => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl IntoResponse for ExamActionResult {
+    fn into_response(self) -> Response<Body> {
+        (self.status_code(), Json(self)).into_response()
+    }
+}
+
+impl ExamActionResult {
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self.outcome,
+            ExamActionOutcome::Registered
+                | ExamActionOutcome::Cancelled
+                | ExamActionOutcome::AlreadyRegistered
+        ) && self.verified != Some(false)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkExamActionRequest {
+    pub exams: Vec<ExamRegistrationMetadata>,
+    // default: keep going and report every item
+    #[serde(default)]
+    pub stop_on_error: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BulkExamActionOutcome {
+    Done { result: ExamActionResult },
+    Rejected { rejection: ExamActionRejection },
+    Error { error: String },
+    // not attempted because an earlier item failed and stop_on_error was set, or because the
+    // same exam is already earlier in the list
+    Skipped,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BulkExamActionItem {
+    pub exam: ExamRegistrationMetadata,
+    #[serde(flatten)]
+    pub outcome: BulkExamActionOutcome,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExamActionKind {
+    Register,
+    Cancel,
+}
+
+pub enum ExamActionError {
+    Rejected(ExamActionRejection),
+    Upstream(ResponseError),
+}
+
+impl From<ResponseError> for ExamActionError {
+    fn from(e: ResponseError) -> Self {
+        ExamActionError::Upstream(e)
+    }
+}
+
+impl From<anyhow::Error> for ExamActionError {
+    fn from(e: anyhow::Error) -> Self {
+        ExamActionError::Upstream(e.into())
+    }
+}
+
+impl From<reqwest::Error> for ExamActionError {
+    fn from(e: reqwest::Error) -> Self {
+        ExamActionError::Upstream(e.into())
+    }
+}
+
+impl From<reqwest_middleware::Error> for ExamActionError {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        ExamActionError::Upstream(e.into())
+    }
+}
+
+impl From<ExamActionRejection> for ExamActionError {
+    fn from(rejection: ExamActionRejection) -> Self {
+        ExamActionError::Rejected(rejection)
+    }
+}
+
+impl IntoResponse for ExamActionError {
+    fn into_response(self) -> Response<Body> {
+        match self {
+            ExamActionError::Upstream(e) => e.into_response(),
+            ExamActionError::Rejected(rejection) => {
+                let mut body = serde_json::to_value(&rejection).unwrap_or_default();
+                body["error"] = rejection.message().into();
+
+                (rejection.status_code(), Json(body)).into_response()
+            }
+        }
+    }
+}
+
+// offerdetail, without the examorg long text
+pub async fn fetch_exam_details(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    examregist_meta: &ExamRegistrationMetadata,
+) -> Result<CdExamDetails, ResponseError> {
+    Ok(client
+        .get(format!(
+            "https://selfservice.campus-dual.de/acwork/offerdetail?user={}&objidexm=undefined&evob_objid={}&peryr={}&perid={}&offerno={}",
+            cd_auth_data.user,
+            examregist_meta.assessment,
+            examregist_meta.peryr,
+            examregist_meta.perid,
+            examregist_meta.offerno,
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+pub async fn fetch_exam_signup_options(
+    client: &ClientWithMiddleware,
+) -> Result<ExamTableExtraction<CampusDualSignupOption>, ResponseError> {
+    let exam_signup_html = client
+        .get("https://selfservice.campus-dual.de/acwork/expproc")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(extract_exam_signup_options(exam_signup_html).await?)
+}
+
+pub async fn fetch_exam_verfahren_options(
+    client: &ClientWithMiddleware,
+) -> Result<ExamTableExtraction<CampusDualVerfahrenOption>, ResponseError> {
+    let exam_verfahren_html = client
+        .get("https://selfservice.campus-dual.de/acwork/cancelproc")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(extract_exam_verfahren_options(exam_verfahren_html).await?)
+}
+
+pub async fn register_exam(
+    cd_auth_data: &CdAuthData,
+    examregist_meta: &ExamRegistrationMetadata,
+) -> Result<ExamActionResult, ExamActionError> {
+    exam_action(cd_auth_data, examregist_meta, ExamActionKind::Register).await
+}
+
+pub async fn cancel_exam(
+    cd_auth_data: &CdAuthData,
+    examregist_meta: &ExamRegistrationMetadata,
+) -> Result<ExamActionResult, ExamActionError> {
+    exam_action(cd_auth_data, examregist_meta, ExamActionKind::Cancel).await
+}
+
+async fn exam_action(
+    cd_auth_data: &CdAuthData,
+    examregist_meta: &ExamRegistrationMetadata,
+    kind: ExamActionKind,
+) -> Result<ExamActionResult, ExamActionError> {
+    let cookie_client = get_client_with_cd_cookie(true, cd_auth_data.cookie.clone())?;
+    let preflight = PreflightOptions::fetch(&cookie_client, kind).await?;
+    preflight.check(examregist_meta)?;
+
+    let (outcome, raw_response) = send_exam_action(cd_auth_data, examregist_meta, kind).await?;
+    let verfahren_options = fetch_exam_verfahren_options(&cookie_client)
+        .await
+        .ok()
+        .map(|extraction| extraction.options);
+
+    Ok(verify_outcome(
+        outcome,
+        kind,
+        is_in_cancel_list(verfahren_options.as_deref(), examregist_meta),
+        raw_response,
+    ))
+}
+
+// the classified response and the raw one, client needs no cookie
+async fn send_exam_action(
+    cd_auth_data: &CdAuthData,
+    examregist_meta: &ExamRegistrationMetadata,
+    kind: ExamActionKind,
+) -> Result<(ExamActionOutcome, String), ExamActionError> {
+    let url = match kind {
+        ExamActionKind::Register => format!(
+            "https://selfservice.campus-dual.de/acwork/registerexam?userid={}&assessment={}&peryr={}&perid={}&offerno={}&hash={}",
+            cd_auth_data.user,
+            examregist_meta.assessment,
+            examregist_meta.peryr,
+            examregist_meta.perid,
+            examregist_meta.offerno,
+            cd_auth_data.hash,
+        ),
+        ExamActionKind::Cancel => format!(
+            "https://selfservice.campus-dual.de/acwork/cancelexam?userid={}&objid={}&hash={}",
+            cd_auth_data.user, examregist_meta.assessment, cd_auth_data.hash
+        ),
+    };
+
+    // writes are never retried, CampusDual might have processed the first attempt
+    let client = get_client_default(false)?;
+    let raw_response = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok((classify_response(&raw_response, kind), raw_response))
+}
+
+// Sequential on purpose: CampusDual does not cope well with parallel writes for one user.
+// The exam lists are read once before and once after all writes, not per exam.
+pub async fn bulk_exam_action(
+    cd_auth_data: &CdAuthData,
+    request: BulkExamActionRequest,
+    kind: ExamActionKind,
+) -> Result<Vec<BulkExamActionItem>, ResponseError> {
+    check_batch_len(request.exams.len(), MAX_BULK_EXAMS)?;
+
+    let cookie_client = get_client_with_cd_cookie(true, cd_auth_data.cookie.clone())?;
+    let preflight = PreflightOptions::fetch(&cookie_client, kind).await?;
+
+    // sent items keep their classified outcome until the lists are re-read
+    let mut results = Vec::with_capacity(request.exams.len());
+    let mut stopped = false;
+    let mut sent = 0;
+
+    for exam in request.exams {
+        // the preflight lists don't know about the writes of this batch
+        if stopped || results.iter().any(|(seen, _)| *seen == exam) {
+            results.push((exam, Err(BulkExamActionOutcome::Skipped)));
+            continue;
+        }
+
+        if let Err(rejection) = preflight.check(&exam) {
+            stopped = request.stop_on_error;
+            results.push((exam, Err(BulkExamActionOutcome::Rejected { rejection })));
+            continue;
+        }
+
+        if sent > 0 {
+            tokio::time::sleep(BULK_WRITE_DELAY).await;
+        }
+        sent += 1;
+
+        match send_exam_action(cd_auth_data, &exam, kind).await {
+            Ok((outcome, raw_response)) => {
+                // an unclear answer is only known to be a failure after re-reading the lists,
+                // which is only worth an extra call if the batch might have to stop
+                if request.stop_on_error {
+                    let verfahren_options = match outcome {
+                        ExamActionOutcome::Unknown => fetch_exam_verfahren_options(&cookie_client)
+                            .await
+                            .ok()
+                            .map(|extraction| extraction.options),
+                        _ => None,
+                    };
+                    let result = verify_outcome(
+                        outcome,
+                        kind,
+                        is_in_cancel_list(verfahren_options.as_deref(), &exam),
+                        raw_response.clone(),
+                    );
+                    stopped = !result.is_success();
+                }
+                results.push((exam, Ok((outcome, raw_response))));
+            }
+            Err(e) => {
+                stopped = request.stop_on_error;
+                let error = match e {
+                    ExamActionError::Rejected(rejection) => {
+                        BulkExamActionOutcome::Rejected { rejection }
+                    }
+                    ExamActionError::Upstream(e) => {
+                        BulkExamActionOutcome::Error { error: e.message }
+                    }
+                };
+                results.push((exam, Err(error)));
+            }
+        }
+    }
+
+    let verfahren_options = if sent > 0 {
+        fetch_exam_verfahren_options(&cookie_client)
+            .await
+            .ok()
+            .map(|extraction| extraction.options)
+    } else {
+        None
+    };
+
+    Ok(results
+        .into_iter()
+        .map(|(exam, result)| {
+            let outcome = match result {
+                Ok((outcome, raw_response)) => BulkExamActionOutcome::Done {
+                    result: verify_outcome(
+                        outcome,
+                        kind,
+                        is_in_cancel_list(verfahren_options.as_deref(), &exam),
+                        raw_response,
+                    ),
+                },
+                Err(outcome) => outcome,
+            };
+            BulkExamActionItem { exam, outcome }
+        })
+        .collect())
+}
+
+// SAP answers with free-form (german) text, so this is keyword based
+fn classify_response(raw_response: &str, kind: ExamActionKind) -> ExamActionOutcome {
+    const NEGATIONS: [&str; 7] = [
+        "nicht angemeldet",
+        "nicht gebucht",
+        "nicht abgemeldet",
+        "nicht storniert",
+        "nicht möglich",
+        "konnte nicht",
+        "fehler",
+    ];
+    const REGISTER_SUCCESS: [&str; 3] = ["angemeldet", "gebucht", "anmeldung erfolgreich"];
+    const CANCEL_SUCCESS: [&str; 3] = ["abgemeldet", "storniert", "abmeldung erfolgreich"];
+
+    let text = raw_response.to_lowercase();
+    let contains_any = |keywords: &[&str]| keywords.iter().any(|keyword| text.contains(keyword));
+
+    if matches!(kind, ExamActionKind::Register)
+        && text.contains("bereits")
+        && contains_any(&["angemeldet", "gebucht"])
+    {
+        ExamActionOutcome::AlreadyRegistered
+    } else if contains_any(&["frist", "abgelaufen", "nicht mehr"]) {
+        ExamActionOutcome::DeadlinePassed
+    } else if contains_any(&[
+        "nicht zugelassen",
+        "berechtigung",
+        "nicht berechtigt",
+        "voraussetzung",
+    ]) {
+        ExamActionOutcome::NotEligible
+    } else if contains_any(&NEGATIONS) {
+        // "Sie sind nicht angemeldet" is no success, whatever else the text says
+        ExamActionOutcome::Unknown
+    } else {
+        // a register response talking about a cancellation (or the other way round) is odd
+        let (success, own, other) = match kind {
+            ExamActionKind::Register => (
+                ExamActionOutcome::Registered,
+                REGISTER_SUCCESS,
+                CANCEL_SUCCESS,
+            ),
+            ExamActionKind::Cancel => (
+                ExamActionOutcome::Cancelled,
+                CANCEL_SUCCESS,
+                REGISTER_SUCCESS,
+            ),
+        };
+        if contains_any(&own) && !contains_any(&other) {
+            success
+        } else {
+            ExamActionOutcome::Unknown
+        }
+    }
+}
+
+// the re-read exam lists are the ground truth, the response text only fills the gaps
+fn verify_outcome(
+    outcome: ExamActionOutcome,
+    kind: ExamActionKind,
+    registered: Option<bool>,
+    raw_response: String,
+) -> ExamActionResult {
+    // registered exams move from the signup list to the cancellation list
+    let (success, succeeded) = match kind {
+        ExamActionKind::Register => (ExamActionOutcome::Registered, registered),
+        ExamActionKind::Cancel => (
+            ExamActionOutcome::Cancelled,
+            registered.map(|registered| !registered),
+        ),
+    };
+
+    let (outcome, verified) = match (outcome, succeeded) {
+        (ExamActionOutcome::AlreadyRegistered, Some(true)) => {
+            (ExamActionOutcome::AlreadyRegistered, Some(true))
+        }
+        (_, Some(true)) => (success, Some(true)),
+        (outcome, Some(false)) if outcome == success => (outcome, Some(false)),
+        (outcome, Some(false)) => (outcome, Some(true)),
+        (outcome, None) => (outcome, None),
+    };
+
+    ExamActionResult {
+        outcome,
+        verified,
+        raw_response,
+    }
+}
+
+// None if the cancellation list could not be read
+fn is_in_cancel_list(
+    verfahren_options: Option<&[CampusDualVerfahrenOption]>,
+    examregist_meta: &ExamRegistrationMetadata,
+) -> Option<bool> {
+    Some(verfahren_options?.iter().any(|option| {
+        option
+            .internal_metadata
+            .as_ref()
+            .is_some_and(|meta| meta.assessment == examregist_meta.assessment)
+    }))
+}
+
+// the list an action is offered in: signup list for registrations, cancel list for cancellations
+enum PreflightOptions {
+    Register(Vec<CampusDualSignupOption>),
+    Cancel(Vec<CampusDualVerfahrenOption>),
+}
+
+impl PreflightOptions {
+    // client needs the CD cookie
+    async fn fetch(
+        client: &ClientWithMiddleware,
+        kind: ExamActionKind,
+    ) -> Result<Self, ResponseError> {
+        Ok(match kind {
+            ExamActionKind::Register => {
+                PreflightOptions::Register(fetch_exam_signup_options(client).await?.options)
+            }
+            ExamActionKind::Cancel => {
+                PreflightOptions::Cancel(fetch_exam_verfahren_options(client).await?.options)
+            }
+        })
+    }
+
+    fn check(&self, examregist_meta: &ExamRegistrationMetadata) -> Result<(), ExamActionRejection> {
+        match self {
+            PreflightOptions::Register(signup_options) => {
+                let option = find_option(
+                    signup_options
+                        .iter()
+                        .map(|option| (option.internal_metadata.as_ref(), option)),
+                    examregist_meta,
+                )?;
+                check_status(&option.status_kind, ExamActionKind::Register)?;
+                check_deadline(option.signup_until_date)
+            }
+            PreflightOptions::Cancel(verfahren_options) => {
+                let option = find_option(
+                    verfahren_options
+                        .iter()
+                        .map(|option| (option.internal_metadata.as_ref(), option)),
+                    examregist_meta,
+                )?;
+                check_status(&option.status_kind, ExamActionKind::Cancel)?;
+                check_deadline(option.signoff_until_date)
+            }
+        }
+    }
+}
+
+fn find_option<'a, T>(
+    options: impl Iterator<Item = (Option<&'a ExamRegistrationMetadata>, &'a T)>,
+    examregist_meta: &ExamRegistrationMetadata,
+) -> Result<&'a T, ExamActionRejection> {
+    let mut same_assessment = None;
+
+    for (meta, option) in options {
+        let Some(meta) = meta else {
+            continue;
+        };
+        if meta.assessment != examregist_meta.assessment {
+            continue;
+        }
+        if meta.peryr == examregist_meta.peryr
+            && meta.perid == examregist_meta.perid
+            && meta.offerno == examregist_meta.offerno
+        {
+            return Ok(option);
+        }
+        same_assessment = Some(meta);
+    }
+
+    Err(match same_assessment {
+        Some(current) => ExamActionRejection::OfferMismatch {
+            current: current.clone(),
+        },
+        None => ExamActionRejection::NotOffered,
+    })
+}
+
+// missed exam dates can't be booked or cancelled anymore, booked ones not booked again and
+// only booked ones cancelled
+fn check_status(status: &ExamStatus, kind: ExamActionKind) -> Result<(), ExamActionRejection> {
+    let allowed = match kind {
+        ExamActionKind::Register => !matches!(status, ExamStatus::Missed | ExamStatus::Registered),
+        ExamActionKind::Cancel => *status == ExamStatus::Registered,
+    };
+    if !allowed {
+        return Err(ExamActionRejection::StatusForbids {
+            status: status.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+// deadlines are inclusive ("bis 12.03.2024")
+fn check_deadline(deadline: Option<NaiveDate>) -> Result<(), ExamActionRejection> {
+    if let Some(deadline) = deadline {
+        if berlin_now().date_naive() > deadline {
+            return Err(ExamActionRejection::DeadlinePassed { deadline });
+        }
+    }
+
+    Ok(())
+}
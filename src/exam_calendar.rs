@@ -0,0 +1,333 @@
+use axum::{
+    extract::Query,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::{Europe::Berlin, Tz};
+use futures::{stream, StreamExt};
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    campus_backend::req_client_funcs::{get_client_default, get_client_with_cd_cookie},
+    constants::BATCH_CONCURRENCY,
+    exam_actions::{fetch_exam_signup_options, fetch_exam_verfahren_options},
+    exam_details::fetch_exam_offer,
+    ics_stuff::{IcsAlarm, IcsCalendar, IcsEvent},
+    time_stuff::{parse_sap_date, parse_sap_time},
+    types::{
+        CdAuthData, CdExamDetails, ExamCalendarEvent, ExamCalendarEventKind, ExamCalendarFormat,
+        ExamCalendarQuery, ExamRegistrationMetadata, ResponseError,
+    },
+};
+
+// used when neither the table nor the offer details contain an end time
+const DEFAULT_EXAM_MINUTES: i64 = 90;
+
+// what the signup and cancel tables know about an exam
+#[derive(Clone)]
+pub struct ExamListing {
+    pub name: String,
+    pub registered: bool,
+    pub date: Option<NaiveDate>,
+    pub start: Option<NaiveTime>,
+    pub end: Option<NaiveTime>,
+    pub room: Option<String>,
+    // signup_until for open exams, signoff_until for registered ones
+    pub table_deadline: Option<NaiveDate>,
+    pub metadata: Option<ExamRegistrationMetadata>,
+}
+
+pub async fn get_exam_calendar(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Query(calendar_query): Query<ExamCalendarQuery>,
+) -> Result<Response, ResponseError> {
+    let events = fetch_exam_calendar_events(&cd_auth_data).await?;
+
+    Ok(match calendar_query.format {
+        ExamCalendarFormat::Json => Json(events).into_response(),
+        ExamCalendarFormat::Ics => (
+            [
+                (CONTENT_TYPE, "text/calendar; charset=utf-8"),
+                (
+                    CONTENT_DISPOSITION,
+                    "attachment; filename=\"pruefungen.ics\"",
+                ),
+            ],
+            exam_events_to_ics(&events),
+        )
+            .into_response(),
+    })
+}
+
+pub async fn fetch_exam_calendar_events(
+    cd_auth_data: &CdAuthData,
+) -> Result<Vec<ExamCalendarEvent>, ResponseError> {
+    let cookie_client = get_client_with_cd_cookie(true, cd_auth_data.cookie.clone())?;
+    let listings = fetch_exam_listings(&cookie_client).await?;
+
+    exam_events_for_listings(listings, cd_auth_data).await
+}
+
+// registered exams (cancel list) first, then the ones still open for registration
+pub async fn fetch_exam_listings(
+    cookie_client: &ClientWithMiddleware,
+) -> Result<Vec<ExamListing>, ResponseError> {
+    let mut listings = fetch_exam_verfahren_options(cookie_client)
+        .await?
+        .options
+        .into_iter()
+        .map(|option| ExamListing {
+            name: option.name,
+            registered: true,
+            date: option.exam_date_parsed,
+            start: option.exam_start,
+            end: option.exam_end,
+            room: option.exam_room,
+            table_deadline: option.signoff_until_date,
+            metadata: option.internal_metadata,
+        })
+        .collect::<Vec<_>>();
+
+    // registered exams can show up in the signup list as well
+    for option in fetch_exam_signup_options(cookie_client).await?.options {
+        let known = listings.iter().any(|listing| match &listing.metadata {
+            Some(metadata) => option.internal_metadata.as_ref() == Some(metadata),
+            None => listing.name == option.name && listing.date == option.exam_date_parsed,
+        });
+        if !known {
+            listings.push(ExamListing {
+                name: option.name,
+                registered: false,
+                date: option.exam_date_parsed,
+                start: option.exam_start,
+                end: option.exam_end,
+                room: option.exam_room,
+                table_deadline: option.signup_until_date,
+                metadata: option.internal_metadata,
+            });
+        }
+    }
+
+    Ok(listings)
+}
+
+pub async fn exam_events_for_listings(
+    listings: Vec<ExamListing>,
+    cd_auth_data: &CdAuthData,
+) -> Result<Vec<ExamCalendarEvent>, ResponseError> {
+    let client = get_client_default(true)?;
+    let concurrency = (*BATCH_CONCURRENCY.get().unwrap()).max(1);
+    let mut events = stream::iter(listings)
+        .map(|listing| {
+            let client = &client;
+            async move { listing_events(client, cd_auth_data, &listing).await }
+        })
+        .buffer_unordered(concurrency)
+        .flat_map(stream::iter)
+        .collect::<Vec<_>>()
+        .await;
+
+    events.sort_by_key(|event| event.start);
+    Ok(events)
+}
+
+// the offer details have the deadlines, without them only the exam itself is known
+async fn listing_events(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    listing: &ExamListing,
+) -> Vec<ExamCalendarEvent> {
+    let details = match &listing.metadata {
+        Some(metadata) => fetch_exam_offer(client, cd_auth_data, metadata)
+            .await
+            .inspect_err(|e| {
+                log::warn!(
+                    "Exam calendar: no details for {}: {}",
+                    listing.name,
+                    e.message
+                )
+            })
+            .ok(),
+        None => None,
+    };
+
+    exam_events(listing, details.as_ref())
+}
+
+fn exam_events(listing: &ExamListing, details: Option<&CdExamDetails>) -> Vec<ExamCalendarEvent> {
+    let uid_base = match &listing.metadata {
+        Some(meta) => format!(
+            "{}-{}-{}-{}",
+            meta.assessment, meta.peryr, meta.perid, meta.offerno
+        ),
+        None => listing
+            .name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect(),
+    };
+    let event = |kind: ExamCalendarEventKind,
+                 title: String,
+                 start: DateTime<Tz>,
+                 end: Option<DateTime<Tz>>,
+                 location: Option<String>,
+                 alarm_minutes_before: Vec<i64>| ExamCalendarEvent {
+        uid: format!("{uid_base}-{}@campus-api", kind_uid(kind)),
+        kind,
+        title,
+        exam_name: listing.name.clone(),
+        registered: listing.registered,
+        start,
+        end,
+        location,
+        alarm_minutes_before,
+        internal_metadata: listing.metadata.clone(),
+    };
+
+    let mut events = Vec::new();
+
+    // the offer details are more precise than the table, fall back to the table otherwise
+    let exam_date = details
+        .and_then(|details| parse_sap_date(&details.ev_examdate))
+        .or(listing.date);
+    let exam_start_time = details
+        .and_then(|details| parse_sap_time(&details.ev_exambegtime))
+        .filter(|time| *time != NaiveTime::MIN)
+        .or(listing.start);
+    let exam_end_time = details
+        .and_then(|details| parse_sap_time(&details.ev_examendtime))
+        .filter(|time| *time != NaiveTime::MIN)
+        .or(listing.end);
+    let location = details
+        .map(|details| details.ev_room_stext.trim())
+        .filter(|room| !room.is_empty())
+        .map(|room| room.to_string())
+        .or_else(|| listing.room.clone());
+
+    if let Some(start) = exam_date
+        .zip(exam_start_time)
+        .and_then(|(date, time)| to_berlin(date.and_time(time)))
+    {
+        let end = exam_date
+            .zip(exam_end_time)
+            .and_then(|(date, time)| to_berlin(date.and_time(time)))
+            .filter(|end| *end > start)
+            .unwrap_or(start + Duration::minutes(DEFAULT_EXAM_MINUTES));
+
+        events.push(event(
+            ExamCalendarEventKind::Exam,
+            format!("Prüfung: {}", listing.name),
+            start,
+            Some(end),
+            location,
+            vec![24 * 60, 2 * 60],
+        ));
+    }
+
+    let Some(details) = details else {
+        return events;
+    };
+
+    // registration deadlines only matter until registered, the deregistration deadline after that
+    if listing.registered {
+        if let Some(deadline) = sap_deadline(&details.ev_dereg_end, &details.ev_dereg_endtime, true)
+        {
+            events.push(event(
+                ExamCalendarEventKind::DeregistrationEnd,
+                format!("Abmeldeschluss: {}", listing.name),
+                deadline,
+                None,
+                None,
+                vec![7 * 24 * 60, 3 * 24 * 60, 24 * 60],
+            ));
+        }
+    } else {
+        if let Some(begin) = sap_deadline(&details.ev_regis_begin, &details.ev_regis_begtime, false)
+        {
+            events.push(event(
+                ExamCalendarEventKind::RegistrationBegin,
+                format!("Anmeldebeginn: {}", listing.name),
+                begin,
+                None,
+                None,
+                vec![0],
+            ));
+        }
+        if let Some(deadline) = sap_deadline(&details.ev_regis_end, &details.ev_regis_endtime, true)
+        {
+            events.push(event(
+                ExamCalendarEventKind::RegistrationEnd,
+                format!("Anmeldeschluss: {}", listing.name),
+                deadline,
+                None,
+                None,
+                vec![3 * 24 * 60, 24 * 60],
+            ));
+        }
+    }
+
+    events
+}
+
+// SAP leaves the time at 000000 when only a date is set, an end date then lasts the whole day
+pub fn sap_deadline(date: &str, time: &str, is_end: bool) -> Option<DateTime<Tz>> {
+    let date = parse_sap_date(date)?;
+    let time = match parse_sap_time(time) {
+        Some(time) if time != NaiveTime::MIN => time,
+        _ if is_end => NaiveTime::from_hms_opt(23, 59, 0)?,
+        _ => NaiveTime::MIN,
+    };
+    to_berlin(date.and_time(time))
+}
+
+fn to_berlin(local: NaiveDateTime) -> Option<DateTime<Tz>> {
+    Berlin.from_local_datetime(&local).earliest()
+}
+
+pub fn kind_uid(kind: ExamCalendarEventKind) -> &'static str {
+    match kind {
+        ExamCalendarEventKind::Exam => "exam",
+        ExamCalendarEventKind::RegistrationBegin => "regis-begin",
+        ExamCalendarEventKind::RegistrationEnd => "regis-end",
+        ExamCalendarEventKind::DeregistrationEnd => "dereg-end",
+    }
+}
+
+pub fn exam_events_to_ics(events: &[ExamCalendarEvent]) -> String {
+    let mut calendar = IcsCalendar::new("CampusDual Prüfungen");
+
+    calendar.events = events.iter().map(exam_event_to_ics).collect();
+    calendar.render()
+}
+
+pub fn exam_event_to_ics(exam_event: &ExamCalendarEvent) -> IcsEvent {
+    let mut event = IcsEvent::new(
+        exam_event.uid.clone(),
+        exam_event.title.clone(),
+        &exam_event.start,
+    );
+    event.end = exam_event.end.map(|end| end.with_timezone(&Utc));
+    event.location = exam_event.location.clone();
+    event.description = Some(format!(
+        "{} ({})",
+        exam_event.exam_name,
+        if exam_event.registered {
+            "angemeldet"
+        } else {
+            "nicht angemeldet"
+        }
+    ));
+    event.categories = vec![kind_uid(exam_event.kind).to_string()];
+    event.alarms = exam_event
+        .alarm_minutes_before
+        .iter()
+        .map(|minutes_before| IcsAlarm {
+            minutes_before: *minutes_before,
+            description: exam_event.title.clone(),
+        })
+        .collect();
+
+    event
+}
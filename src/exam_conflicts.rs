@@ -0,0 +1,199 @@
+use axum::{Extension, Json};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::{Europe::Berlin, Tz};
+
+use crate::{
+    campus_backend::req_client_funcs::{get_client_default, get_client_with_cd_cookie},
+    exam_actions::{fetch_exam_signup_options, fetch_exam_verfahren_options},
+    stundenplan::fetch_stundenplan,
+    types::{
+        CdAuthData, ExamConflict, ExamConflictReport, ExamRoom, ResponseError, ScheduledExam,
+        StundenplanItem,
+    },
+};
+
+// used when CampusDual only publishes a start time
+const DEFAULT_EXAM_MINUTES: i64 = 90;
+// exams closer than this in different buildings are reported as back-to-back
+const MIN_BUILDING_CHANGE_MINUTES: i64 = 30;
+
+pub async fn get_examconflicts(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+) -> Result<Json<ExamConflictReport>, ResponseError> {
+    let cookie_client = get_client_with_cd_cookie(true, cd_auth_data.cookie.clone())?;
+
+    let signup_options = fetch_exam_signup_options(&cookie_client).await?.options;
+    let verfahren_options = fetch_exam_verfahren_options(&cookie_client).await?.options;
+
+    let mut exams: Vec<ScheduledExam> = verfahren_options
+        .iter()
+        .filter_map(|option| {
+            scheduled_exam(
+                &option.name,
+                &option.verfahren,
+                true,
+                option.exam_date_parsed,
+                (option.exam_start, option.exam_end),
+                option.exam_room_parsed.as_ref(),
+            )
+        })
+        .collect();
+
+    // exams that are registered already show up in both lists
+    for option in &signup_options {
+        let Some(exam) = scheduled_exam(
+            &option.name,
+            &option.verfahren,
+            false,
+            option.exam_date_parsed,
+            (option.exam_start, option.exam_end),
+            option.exam_room_parsed.as_ref(),
+        ) else {
+            continue;
+        };
+        if !exams
+            .iter()
+            .any(|known| known.verfahren == exam.verfahren && known.start == exam.start)
+        {
+            exams.push(exam);
+        }
+    }
+    exams.sort_by_key(|exam| exam.start);
+
+    let lectures = match (exams.first(), exams.iter().map(|exam| exam.end).max()) {
+        (Some(first), Some(last_end)) => {
+            let client = get_client_default(true)?;
+            fetch_stundenplan(
+                &client,
+                &cd_auth_data,
+                first.start.timestamp(),
+                last_end.timestamp(),
+            )
+            .await?
+        }
+        _ => Vec::new(),
+    };
+
+    let conflicts = find_conflicts(&exams, &lectures);
+
+    Ok(Json(ExamConflictReport { exams, conflicts }))
+}
+
+fn scheduled_exam(
+    name: &str,
+    verfahren: &str,
+    registered: bool,
+    date: Option<NaiveDate>,
+    (start_time, end_time): (Option<NaiveTime>, Option<NaiveTime>),
+    room: Option<&ExamRoom>,
+) -> Option<ScheduledExam> {
+    let date = date?;
+    let start = Berlin
+        .from_local_datetime(&date.and_time(start_time?))
+        .earliest()?;
+    let end = match end_time {
+        Some(end_time) => Berlin
+            .from_local_datetime(&date.and_time(end_time))
+            .earliest()?,
+        None => start + Duration::minutes(DEFAULT_EXAM_MINUTES),
+    };
+
+    Some(ScheduledExam {
+        name: name.to_string(),
+        verfahren: verfahren.to_string(),
+        registered,
+        start,
+        end,
+        end_estimated: end_time.is_none(),
+        room: room.cloned(),
+    })
+}
+
+fn find_conflicts(exams: &[ScheduledExam], lectures: &[StundenplanItem]) -> Vec<ExamConflict> {
+    let mut conflicts = Vec::new();
+
+    for (i, first) in exams.iter().enumerate() {
+        for second in &exams[i + 1..] {
+            if first.start < second.end && second.start < first.end {
+                conflicts.push(ExamConflict::ExamOverlap {
+                    first: first.name.clone(),
+                    second: second.name.clone(),
+                });
+                continue;
+            }
+
+            let gap_minutes = (second.start - first.end).num_minutes();
+            if !(0..MIN_BUILDING_CHANGE_MINUTES).contains(&gap_minutes) {
+                continue;
+            }
+            if let (Some(first_room), Some(second_room)) = (&first.room, &second.room) {
+                if building(first_room) != building(second_room) {
+                    conflicts.push(ExamConflict::BackToBack {
+                        first: first.name.clone(),
+                        second: second.name.clone(),
+                        gap_minutes,
+                        first_room: first_room.name.clone(),
+                        second_room: second_room.name.clone(),
+                    });
+                }
+            }
+        }
+
+        for lecture in lectures {
+            let (Some(lecture_start), Some(lecture_end)) = (
+                Berlin.timestamp_opt(lecture.start, 0).single(),
+                Berlin.timestamp_opt(lecture.end, 0).single(),
+            ) else {
+                continue;
+            };
+
+            // the exam itself is usually part of the timetable as well, not always with the
+            // same times (estimated end, different granularity)
+            if is_exam_entry(first, lecture, lecture_start, lecture_end) {
+                continue;
+            }
+
+            if first.start < lecture_end && lecture_start < first.end {
+                conflicts.push(ExamConflict::LectureClash {
+                    exam: first.name.clone(),
+                    lecture: lecture.title.clone(),
+                    lecture_start,
+                    lecture_end,
+                    lecture_room: lecture.room.clone(),
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+fn is_exam_entry(
+    exam: &ScheduledExam,
+    lecture: &StundenplanItem,
+    lecture_start: DateTime<Tz>,
+    lecture_end: DateTime<Tz>,
+) -> bool {
+    let covers_exam_start = lecture_start <= exam.start && exam.start < lecture_end;
+    if lecture.color == "darkred" && covers_exam_start {
+        return true;
+    }
+
+    // a regular lecture of the same module can share the name, so only an entry in the
+    // exam's own slot counts
+    let same_slot = lecture_start.date_naive() == exam.start.date_naive()
+        && exam.start < lecture_end
+        && lecture_start < exam.end;
+    let (title, exam_name) = (
+        lecture.title.trim().to_lowercase(),
+        exam.name.trim().to_lowercase(),
+    );
+    same_slot && !exam_name.is_empty() && title.contains(&exam_name)
+}
+
+// rooms without a recognizable building are compared by their full name
+fn building(room: &ExamRoom) -> String {
+    room.building
+        .clone()
+        .unwrap_or_else(|| room.name.to_uppercase())
+}
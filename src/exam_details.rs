@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    cache_stuff::TtlCache,
+    constants::{EXAM_CACHE_MAX_ENTRIES, EXAM_CACHE_TTL_SEC},
+    exam_actions::fetch_exam_details,
+    types::{CdAuthData, CdExamDetails, ExamRegistrationMetadata, ResponseError},
+};
+
+lazy_static! {
+    // examorg long texts are the same for every student
+    static ref EXAMORG_CACHE: TtlCache<String> = new_exam_cache();
+    // offer details with the personal fields removed, see CdExamDetails::without_personal_data
+    static ref OFFER_CACHE: TtlCache<CdExamDetails> = new_exam_cache();
+}
+
+fn new_exam_cache<V: Clone>() -> TtlCache<V> {
+    TtlCache::new(
+        Duration::from_secs(*EXAM_CACHE_TTL_SEC.get().unwrap()),
+        *EXAM_CACHE_MAX_ENTRIES.get().unwrap(),
+    )
+}
+
+pub fn offer_key(examregist_meta: &ExamRegistrationMetadata) -> String {
+    format!(
+        "{}/{}/{}/{}",
+        examregist_meta.assessment,
+        examregist_meta.peryr,
+        examregist_meta.perid,
+        examregist_meta.offerno
+    )
+}
+
+// Full details for the current user, always fresh since they contain personal fields
+pub async fn fetch_exam_details_with_longtext(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    examregist_meta: &ExamRegistrationMetadata,
+) -> Result<CdExamDetails, ResponseError> {
+    let mut exam_details = fetch_exam_details(client, cd_auth_data, examregist_meta).await?;
+    OFFER_CACHE.insert(
+        offer_key(examregist_meta),
+        exam_details.without_personal_data(),
+    );
+
+    exam_details.ev_examorg_longtext =
+        Some(fetch_examorg_longtext(client, &exam_details.ev_examorg_text).await?);
+    Ok(exam_details)
+}
+
+// Offer details without personal fields, shared between all users
+pub async fn fetch_exam_offer(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    examregist_meta: &ExamRegistrationMetadata,
+) -> Result<CdExamDetails, ResponseError> {
+    let key = offer_key(examregist_meta);
+    if let Some(offer) = OFFER_CACHE.get(&key) {
+        return Ok(offer);
+    }
+
+    let offer = fetch_exam_details(client, cd_auth_data, examregist_meta)
+        .await?
+        .without_personal_data();
+    OFFER_CACHE.insert(key, offer.clone());
+    Ok(offer)
+}
+
+// falls back to the short text if CampusDual has no long text, that fallback isn't cached
+async fn fetch_examorg_longtext(
+    client: &ClientWithMiddleware,
+    examorg_text: &str,
+) -> Result<String, ResponseError> {
+    if let Some(longtext) = EXAMORG_CACHE.get(examorg_text) {
+        return Ok(longtext);
+    }
+
+    let resp = client
+        .get(format!(
+            "https://selfservice.campus-dual.de/acwork/examorg?examorg={examorg_text}"
+        ))
+        .send()
+        .await?
+        .error_for_status();
+    match resp {
+        Ok(resp) => {
+            let longtext: String = serde_json::from_str(&resp.text().await?)?;
+            EXAMORG_CACHE.insert(examorg_text.to_string(), longtext.clone());
+            Ok(longtext)
+        }
+        Err(_) => Ok(examorg_text.to_string()),
+    }
+}
@@ -0,0 +1,374 @@
+// Opt-in (EXAM_SCHEDULER_FILE) queue of exam registrations that are sent as soon as the
+// registration window of an exam opens. Jobs are persisted as JSON, credentials AES encrypted.
+
+use std::{fs, time::Duration};
+
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use lazy_static::lazy_static;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    campus_backend::{login::cdlogin_get_jcookie_and_meta, req_client_funcs::get_client_default},
+    constants::EXAM_SCHEDULER_FILE,
+    encryption::{decrypt_login_data, encrypt_login_data},
+    exam_actions::{
+        fetch_exam_details, register_exam, ExamActionError, ExamActionOutcome, ExamActionRejection,
+        ExamActionResult,
+    },
+    file_stuff::write_json_atomic,
+    time_stuff::{berlin_to_utc, parse_sap_datetime},
+    types::{CdAuthData, ExamRegistrationMetadata, ExamStatus, ResponseError},
+};
+
+const MAX_JOBS_PER_USER: usize = 20;
+const MAX_ATTEMPTS: u32 = 8;
+const WORKER_INTERVAL: Duration = Duration::from_secs(10);
+// finished jobs are kept around this long so their result can still be looked up
+const FINISHED_JOB_RETENTION_DAYS: i64 = 30;
+
+lazy_static! {
+    static ref JOBS: Mutex<Vec<ScheduledExamJob>> = Mutex::new(Vec::new());
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledJobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ScheduledExamJob {
+    id: String,
+    user: String,
+    exam: ExamRegistrationMetadata,
+    exam_name: String,
+    run_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+    status: ScheduledJobStatus,
+    attempts: u32,
+    last_error: Option<String>,
+    result: Option<ExamActionResult>,
+    // AES encrypted CampusLoginData, the job has to log in by itself
+    nonce: String,
+    cipher: String,
+}
+
+// what users get to see of a job (no credentials)
+#[derive(Serialize, Debug)]
+pub struct ScheduledExamJobInfo {
+    pub id: String,
+    pub exam: ExamRegistrationMetadata,
+    pub exam_name: String,
+    pub run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub status: ScheduledJobStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub result: Option<ExamActionResult>,
+}
+
+impl From<&ScheduledExamJob> for ScheduledExamJobInfo {
+    fn from(job: &ScheduledExamJob) -> Self {
+        ScheduledExamJobInfo {
+            id: job.id.clone(),
+            exam: job.exam.clone(),
+            exam_name: job.exam_name.clone(),
+            run_at: job.run_at,
+            created_at: job.created_at,
+            status: job.status,
+            attempts: job.attempts,
+            last_error: job.last_error.clone(),
+            result: job.result.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScheduledJobId {
+    pub id: String,
+}
+
+fn scheduler_file() -> Result<&'static str, ResponseError> {
+    EXAM_SCHEDULER_FILE
+        .get()
+        .and_then(|file| file.as_deref())
+        .ok_or(ResponseError {
+            message: "Exam scheduler is not enabled on this server".to_string(),
+            status_code: StatusCode::NOT_IMPLEMENTED,
+        })
+}
+
+// loads the persisted queue and spawns the worker, no-op if the scheduler is disabled
+pub async fn start() {
+    let Ok(file) = scheduler_file() else {
+        return;
+    };
+
+    let mut jobs: Vec<ScheduledExamJob> = match fs::read_to_string(file) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::error!("Exam scheduler: unable to parse {file}: {e}, starting empty");
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+
+    // jobs that were interrupted by a restart are simply retried
+    for job in jobs
+        .iter_mut()
+        .filter(|job| job.status == ScheduledJobStatus::Running)
+    {
+        job.status = ScheduledJobStatus::Queued;
+    }
+
+    log::info!("Exam scheduler: {} job(s) loaded from {file}", jobs.len());
+    *JOBS.lock().await = jobs;
+
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(WORKER_INTERVAL).await;
+            run_due_jobs().await;
+        }
+    });
+}
+
+fn persist(jobs: &mut Vec<ScheduledExamJob>) {
+    let Ok(file) = scheduler_file() else {
+        return;
+    };
+
+    let retention_cutoff = Utc::now() - chrono::Duration::days(FINISHED_JOB_RETENTION_DAYS);
+    jobs.retain(|job| {
+        matches!(
+            job.status,
+            ScheduledJobStatus::Queued | ScheduledJobStatus::Running
+        ) || job.run_at > retention_cutoff
+    });
+
+    if let Err(e) = write_json_atomic(file, jobs) {
+        log::error!("Exam scheduler: unable to persist jobs: {e}");
+    }
+}
+
+pub async fn post_schedule_registerexam(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(examregist_meta): Json<ExamRegistrationMetadata>,
+) -> Result<Json<ScheduledExamJobInfo>, ResponseError> {
+    scheduler_file()?;
+
+    let client = get_client_default(true)?;
+    let exam_details = fetch_exam_details(&client, &cd_auth_data, &examregist_meta).await?;
+
+    let run_at = parse_sap_datetime(&exam_details.ev_regis_begin, &exam_details.ev_regis_begtime)
+        .and_then(berlin_to_utc)
+        .ok_or(ResponseError {
+            message: "CampusDual does not know when the registration opens".to_string(),
+            status_code: StatusCode::UNPROCESSABLE_ENTITY,
+        })?;
+
+    let (nonce, cipher) = encrypt_login_data(&cd_auth_data)?;
+
+    let job = ScheduledExamJob {
+        id: format!("{:032x}", rand::thread_rng().gen::<u128>()),
+        user: cd_auth_data.user,
+        exam: examregist_meta,
+        exam_name: exam_details.ev_stext,
+        run_at,
+        created_at: Utc::now(),
+        status: ScheduledJobStatus::Queued,
+        attempts: 0,
+        last_error: None,
+        result: None,
+        nonce,
+        cipher,
+    };
+
+    let mut jobs = JOBS.lock().await;
+
+    let active_jobs = jobs.iter().filter(|other| {
+        other.user == job.user
+            && matches!(
+                other.status,
+                ScheduledJobStatus::Queued | ScheduledJobStatus::Running
+            )
+    });
+    if active_jobs.clone().count() >= MAX_JOBS_PER_USER {
+        return Err(ResponseError {
+            message: "Too many scheduled registrations".to_string(),
+            status_code: StatusCode::TOO_MANY_REQUESTS,
+        });
+    }
+    if active_jobs
+        .clone()
+        .any(|other| other.exam.assessment == job.exam.assessment)
+    {
+        return Err(ResponseError {
+            message: "This exam is already scheduled".to_string(),
+            status_code: StatusCode::CONFLICT,
+        });
+    }
+
+    let info = ScheduledExamJobInfo::from(&job);
+    jobs.push(job);
+    persist(&mut jobs);
+
+    Ok(Json(info))
+}
+
+pub async fn get_scheduled_exams(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+) -> Result<Json<Vec<ScheduledExamJobInfo>>, ResponseError> {
+    scheduler_file()?;
+
+    let jobs = JOBS.lock().await;
+    Ok(Json(
+        jobs.iter()
+            .filter(|job| job.user == cd_auth_data.user)
+            .map(ScheduledExamJobInfo::from)
+            .collect(),
+    ))
+}
+
+pub async fn post_cancel_scheduled_exam(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(job_id): Json<ScheduledJobId>,
+) -> Result<Json<ScheduledExamJobInfo>, ResponseError> {
+    scheduler_file()?;
+
+    let mut jobs = JOBS.lock().await;
+    let job = jobs
+        .iter_mut()
+        .find(|job| job.id == job_id.id && job.user == cd_auth_data.user)
+        .ok_or(ResponseError {
+            message: "Scheduled registration not found".to_string(),
+            status_code: StatusCode::NOT_FOUND,
+        })?;
+
+    if job.status != ScheduledJobStatus::Queued {
+        return Err(ResponseError {
+            message: "Only queued registrations can be cancelled".to_string(),
+            status_code: StatusCode::CONFLICT,
+        });
+    }
+
+    job.status = ScheduledJobStatus::Cancelled;
+    let info = ScheduledExamJobInfo::from(&*job);
+    persist(&mut jobs);
+
+    Ok(Json(info))
+}
+
+async fn run_due_jobs() {
+    // claim due jobs, but don't hold the lock during the (slow) CampusDual calls
+    let due_jobs = {
+        let mut jobs = JOBS.lock().await;
+        let now = Utc::now();
+        let due_jobs = jobs
+            .iter_mut()
+            .filter(|job| job.status == ScheduledJobStatus::Queued && job.run_at <= now)
+            .map(|job| {
+                job.status = ScheduledJobStatus::Running;
+                job.attempts += 1;
+                job.clone()
+            })
+            .collect::<Vec<_>>();
+        if !due_jobs.is_empty() {
+            persist(&mut jobs);
+        }
+        due_jobs
+    };
+
+    // one after another, CampusDual does not like parallel writes
+    for job in due_jobs {
+        let attempt = run_job(&job).await;
+
+        let mut jobs = JOBS.lock().await;
+        if let Some(stored_job) = jobs.iter_mut().find(|stored| stored.id == job.id) {
+            match attempt {
+                JobAttempt::Done(result) => {
+                    stored_job.status = if result.is_success() {
+                        ScheduledJobStatus::Succeeded
+                    } else {
+                        ScheduledJobStatus::Failed
+                    };
+                    stored_job.last_error = None;
+                    stored_job.result = Some(result);
+                }
+                JobAttempt::Fatal(message) => {
+                    stored_job.status = ScheduledJobStatus::Failed;
+                    stored_job.last_error = Some(message);
+                }
+                JobAttempt::Retry(message) if stored_job.attempts >= MAX_ATTEMPTS => {
+                    stored_job.status = ScheduledJobStatus::Failed;
+                    stored_job.last_error = Some(message);
+                }
+                JobAttempt::Retry(message) => {
+                    // 15s, 30s, 60s, ... capped at 10 minutes
+                    let backoff = 15 * 2_i64.pow(stored_job.attempts.min(6) - 1);
+                    stored_job.status = ScheduledJobStatus::Queued;
+                    stored_job.run_at = Utc::now() + chrono::Duration::seconds(backoff.min(600));
+                    stored_job.last_error = Some(message);
+                }
+            }
+            log::info!(
+                "Exam scheduler: job {} attempt {} -> {:?}",
+                stored_job.id,
+                stored_job.attempts,
+                stored_job.status
+            );
+        }
+        persist(&mut jobs);
+    }
+}
+
+enum JobAttempt {
+    Done(ExamActionResult),
+    Retry(String),
+    Fatal(String),
+}
+
+async fn run_job(job: &ScheduledExamJob) -> JobAttempt {
+    let Some(login_data) = decrypt_login_data(&job.nonce, &job.cipher) else {
+        return JobAttempt::Fatal("Stored credentials are unreadable".to_string());
+    };
+
+    let cd_auth_data = match cdlogin_get_jcookie_and_meta(login_data).await {
+        Ok((cd_auth_data, _)) => cd_auth_data,
+        Err(e) => return JobAttempt::Retry(format!("CampusDual login failed: {e}")),
+    };
+
+    match register_exam(&cd_auth_data, &job.exam).await {
+        Ok(result) if result.outcome == ExamActionOutcome::Unknown => JobAttempt::Retry(format!(
+            "Unknown CampusDual response: {}",
+            result.raw_response
+        )),
+        // the next attempt reads the lists again and skips the exam if it did go through
+        Ok(result) if result.verified == Some(false) => {
+            JobAttempt::Retry("Registration was not found in the exam lists afterwards".to_string())
+        }
+        Ok(result) => JobAttempt::Done(result),
+        // the signup list is sometimes updated a bit after the official opening time
+        Err(ExamActionError::Rejected(ExamActionRejection::NotOffered)) => {
+            JobAttempt::Retry("Exam is not offered for registration yet".to_string())
+        }
+        // e.g. a previous attempt went through after all
+        Err(ExamActionError::Rejected(ExamActionRejection::StatusForbids {
+            status: ExamStatus::Registered,
+        })) => JobAttempt::Done(ExamActionResult {
+            outcome: ExamActionOutcome::AlreadyRegistered,
+            verified: Some(true),
+            raw_response: String::new(),
+        }),
+        Err(ExamActionError::Rejected(rejection)) => {
+            JobAttempt::Fatal(format!("Registration rejected: {rejection:?}"))
+        }
+        Err(ExamActionError::Upstream(e)) => JobAttempt::Retry(e.message),
+    }
+}
@@ -0,0 +1,13 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use serde::Serialize;
+
+// write + rename, so a crash never leaves a half written file behind
+pub fn write_json_atomic<T: Serialize + ?Sized>(file: &str, value: &T) -> Result<()> {
+    let content = serde_json::to_vec(value)?;
+    let tmp_file = format!("{file}.tmp");
+    fs::write(&tmp_file, content)?;
+    fs::rename(&tmp_file, Path::new(file))?;
+    Ok(())
+}
@@ -0,0 +1,320 @@
+use serde_json::{json, Value};
+
+use crate::{
+    pdf_stuff::{truncate_to_width, PdfDocument, PdfFont, PdfPage, A4_HEIGHT, A4_WIDTH},
+    time_stuff::berlin_now,
+    types::{CampusDualGrade, UserBasicInfo},
+};
+
+const UNOFFICIAL_NOTICE: &str =
+    "Inoffizielle Notenübersicht aus CampusDual - kein offizielles Dokument der Hochschule.";
+
+pub struct TranscriptSummary {
+    pub ects_total: i32,
+    pub modules_passed: usize,
+    pub average_weighted: Option<f32>,
+    pub average_unweighted: Option<f32>,
+}
+
+impl TranscriptSummary {
+    pub fn from_grades(grades: &[CampusDualGrade]) -> Self {
+        let passed = grades
+            .iter()
+            .filter(|grade| grade.total_passed == Some(true))
+            .collect::<Vec<_>>();
+
+        let numeric = passed
+            .iter()
+            .filter_map(|grade| {
+                grade
+                    .grade_value
+                    .numeric()
+                    .map(|value| (value, grade.credit_points))
+            })
+            .collect::<Vec<_>>();
+
+        let ects_weight: i32 = numeric.iter().map(|(_, ects)| ects).sum();
+        let average_weighted = (ects_weight > 0).then(|| {
+            numeric
+                .iter()
+                .map(|(value, ects)| value * *ects as f32)
+                .sum::<f32>()
+                / ects_weight as f32
+        });
+        let average_unweighted = (!numeric.is_empty())
+            .then(|| numeric.iter().map(|(value, _)| value).sum::<f32>() / numeric.len() as f32);
+
+        TranscriptSummary {
+            ects_total: passed.iter().map(|grade| grade.credit_points).sum(),
+            modules_passed: passed.len(),
+            average_weighted,
+            average_unweighted,
+        }
+    }
+}
+
+fn passed_label(passed: Option<bool>) -> &'static str {
+    match passed {
+        Some(true) => "bestanden",
+        Some(false) => "nicht bestanden",
+        None => "offen",
+    }
+}
+
+fn format_average(average: Option<f32>) -> String {
+    average
+        .map(|avg| format!("{avg:.2}").replace('.', ","))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+pub fn grades_to_csv(grades: &[CampusDualGrade]) -> String {
+    fn escape(field: &str) -> String {
+        if field.contains([';', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    // semicolon separated, since the grades themselves use decimal commas
+    let mut csv =
+        String::from("Modul;Teilleistung;Note;Status;ECTS;Beurteilung;Bekanntgabe;Semester\r\n");
+
+    for grade in grades {
+        csv.push_str(
+            &[
+                escape(&grade.name),
+                String::new(),
+                escape(&grade.grade),
+                passed_label(grade.total_passed).to_string(),
+                grade.credit_points.to_string(),
+                String::new(),
+                String::new(),
+                escape(&grade.akad_period),
+            ]
+            .join(";"),
+        );
+        csv.push_str("\r\n");
+
+        for subgrade in &grade.subgrades {
+            csv.push_str(
+                &[
+                    escape(&grade.name),
+                    escape(&subgrade.name),
+                    escape(&subgrade.grade),
+                    passed_label(subgrade.passed).to_string(),
+                    String::new(),
+                    escape(&subgrade.beurteilung),
+                    escape(&subgrade.bekanntgabe),
+                    escape(&subgrade.akad_period),
+                ]
+                .join(";"),
+            );
+            csv.push_str("\r\n");
+        }
+    }
+
+    csv
+}
+
+// schema.org based credential document, deliberately not a (signed) verifiable credential
+pub fn grades_to_jsonld(grades: &[CampusDualGrade], user: &UserBasicInfo) -> Value {
+    let summary = TranscriptSummary::from_grades(grades);
+
+    let modules = grades
+        .iter()
+        .map(|grade| {
+            json!({
+                "@type": "Course",
+                "name": grade.name,
+                "educationalCredentialAwarded": grade.grade,
+                "gradeValue": grade.grade_value,
+                "numberOfCredits": grade.credit_points,
+                "temporalCoverage": grade.akad_period,
+                "status": passed_label(grade.total_passed),
+                "hasPart": grade.subgrades.iter().map(|subgrade| json!({
+                    "@type": "Course",
+                    "name": subgrade.name,
+                    "educationalCredentialAwarded": subgrade.grade,
+                    "gradeValue": subgrade.grade_value,
+                    "assessmentType": subgrade.beurteilung,
+                    "dateCreated": subgrade.bekanntgabe_date,
+                    "temporalCoverage": subgrade.akad_period,
+                    "status": passed_label(subgrade.passed),
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "@context": "https://schema.org",
+        "@type": "EducationalOccupationalCredential",
+        "name": "Transcript of Records (unofficial)",
+        "credentialCategory": "unofficial transcript",
+        "description": UNOFFICIAL_NOTICE,
+        "dateCreated": berlin_now().date_naive().to_string(),
+        "about": {
+            "@type": "Person",
+            "givenName": user.first_name,
+            "familyName": user.last_name,
+            "identifier": user.user,
+            "memberOf": {
+                "@type": "EducationalOrganization",
+                "name": user.seminar_name,
+                "alternateName": user.seminar_group,
+            },
+        },
+        "numberOfCredits": summary.ects_total,
+        "gradeAverageWeighted": summary.average_weighted,
+        "gradeAverageUnweighted": summary.average_unweighted,
+        "hasPart": modules,
+    })
+}
+
+pub fn grades_to_pdf(grades: &[CampusDualGrade], user: &UserBasicInfo) -> Vec<u8> {
+    const MARGIN: f32 = 50.0;
+    const ROW_HEIGHT: f32 = 16.0;
+    const GREY: (u8, u8, u8) = (110, 110, 110);
+    const BLACK: (u8, u8, u8) = (0, 0, 0);
+    const RED: (u8, u8, u8) = (180, 20, 20);
+    // column x positions: module, semester, grade, ects, status
+    const COLS: [f32; 5] = [MARGIN, 330.0, 420.0, 460.0, 495.0];
+
+    let summary = TranscriptSummary::from_grades(grades);
+    let mut document = PdfDocument::default();
+
+    let new_page = |page_no: usize| {
+        let mut page = PdfPage::new(A4_WIDTH, A4_HEIGHT);
+        page.text(
+            MARGIN,
+            40.0,
+            8.0,
+            PdfFont::Bold,
+            RED,
+            "INOFFIZIELL / UNOFFICIAL",
+        );
+        page.text(
+            MARGIN,
+            A4_HEIGHT - 30.0,
+            7.0,
+            PdfFont::Regular,
+            GREY,
+            &format!("{UNOFFICIAL_NOTICE}  Seite {page_no}"),
+        );
+        page
+    };
+
+    let table_header = |page: &mut PdfPage, y: f32| {
+        for (x, label) in COLS
+            .iter()
+            .zip(["Modul", "Semester", "Note", "ECTS", "Status"])
+        {
+            page.text(*x, y, 9.0, PdfFont::Bold, BLACK, label);
+        }
+        page.line(MARGIN, y + 4.0, A4_WIDTH - MARGIN, y + 4.0, 0.5, BLACK);
+    };
+
+    let mut page_no = 1;
+    let mut page = new_page(page_no);
+
+    page.text(MARGIN, 75.0, 18.0, PdfFont::Bold, BLACK, "Notenübersicht");
+    page.text(
+        MARGIN,
+        95.0,
+        10.0,
+        PdfFont::Regular,
+        BLACK,
+        &format!(
+            "{} {} (Matrikelnummer {})",
+            user.first_name, user.last_name, user.user
+        ),
+    );
+    page.text(
+        MARGIN,
+        110.0,
+        10.0,
+        PdfFont::Regular,
+        BLACK,
+        &format!(
+            "Seminargruppe {} - {}",
+            user.seminar_group, user.seminar_name
+        ),
+    );
+    page.text(
+        MARGIN,
+        125.0,
+        10.0,
+        PdfFont::Regular,
+        GREY,
+        &format!("Erstellt am {}", berlin_now().format("%d.%m.%Y")),
+    );
+
+    let mut y = 160.0;
+    table_header(&mut page, y);
+    y += ROW_HEIGHT + 2.0;
+
+    for (row, grade) in grades.iter().enumerate() {
+        if y > A4_HEIGHT - 80.0 {
+            document.add_page(page);
+            page_no += 1;
+            page = new_page(page_no);
+            y = 70.0;
+            table_header(&mut page, y);
+            y += ROW_HEIGHT + 2.0;
+        }
+
+        if row % 2 == 1 {
+            page.fill_rect(
+                MARGIN - 4.0,
+                y - 11.0,
+                A4_WIDTH - 2.0 * MARGIN + 8.0,
+                ROW_HEIGHT,
+                (240, 240, 240),
+            );
+        }
+
+        let status_color = match grade.total_passed {
+            Some(false) => RED,
+            _ => BLACK,
+        };
+        let cells = [
+            truncate_to_width(&grade.name, 9.0, COLS[1] - COLS[0] - 8.0),
+            grade.akad_period.clone(),
+            grade.grade.clone(),
+            grade.credit_points.to_string(),
+            passed_label(grade.total_passed).to_string(),
+        ];
+        for (i, (x, cell)) in COLS.iter().zip(cells.iter()).enumerate() {
+            let color = if i == 4 { status_color } else { BLACK };
+            page.text(*x, y, 9.0, PdfFont::Regular, color, cell);
+        }
+        y += ROW_HEIGHT;
+    }
+
+    if y > A4_HEIGHT - 120.0 {
+        document.add_page(page);
+        page_no += 1;
+        page = new_page(page_no);
+        y = 70.0;
+    }
+
+    y += 10.0;
+    page.line(MARGIN, y - 12.0, A4_WIDTH - MARGIN, y - 12.0, 0.5, BLACK);
+    for line in [
+        format!(
+            "Bestandene Module: {}    ECTS gesamt: {}",
+            summary.modules_passed, summary.ects_total
+        ),
+        format!(
+            "Durchschnitt (ECTS-gewichtet): {}    Durchschnitt (ungewichtet): {}",
+            format_average(summary.average_weighted),
+            format_average(summary.average_unweighted)
+        ),
+    ] {
+        page.text(MARGIN, y, 10.0, PdfFont::Bold, BLACK, &line);
+        y += ROW_HEIGHT;
+    }
+
+    document.add_page(page);
+    document.render()
+}
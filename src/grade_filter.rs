@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+
+use crate::types::{
+    AcademicPeriod, CampusDualGrade, GradeStatusFilter, GradesQuery, GradesSortKey, SortOrder,
+};
+
+pub fn apply_grades_query(
+    grades: Vec<CampusDualGrade>,
+    query: &GradesQuery,
+) -> Vec<CampusDualGrade> {
+    let period_filter = query.period.as_deref().map(|period| {
+        if period.eq_ignore_ascii_case("latest") {
+            PeriodFilter::Parsed(
+                grades
+                    .iter()
+                    .filter_map(|grade| grade.akad_period_parsed)
+                    .max(),
+            )
+        } else {
+            match AcademicPeriod::parse(period) {
+                Some(parsed) => PeriodFilter::Parsed(Some(parsed)),
+                None => PeriodFilter::Raw(period.trim().to_lowercase()),
+            }
+        }
+    });
+    let search = query
+        .search
+        .as_ref()
+        .map(|search| search.trim().to_lowercase());
+
+    let mut grades = grades
+        .into_iter()
+        .filter(|grade| match &period_filter {
+            None => true,
+            Some(PeriodFilter::Parsed(period)) => {
+                period.is_some() && grade.akad_period_parsed == *period
+            }
+            Some(PeriodFilter::Raw(raw)) => grade.akad_period.trim().to_lowercase() == *raw,
+        })
+        .filter(|grade| match query.status {
+            None => true,
+            Some(GradeStatusFilter::Passed) => grade.total_passed == Some(true),
+            Some(GradeStatusFilter::Failed) => grade.total_passed == Some(false),
+            Some(GradeStatusFilter::Pending) => grade.total_passed.is_none(),
+        })
+        .filter(|grade| match &search {
+            None => true,
+            Some(search) => {
+                grade.name.to_lowercase().contains(search)
+                    || grade
+                        .subgrades
+                        .iter()
+                        .any(|subgrade| subgrade.name.to_lowercase().contains(search))
+            }
+        })
+        .filter(|grade| query.min_ects.is_none_or(|min| grade.credit_points >= min))
+        .collect::<Vec<_>>();
+
+    if let Some(sort) = query.sort {
+        // dates are most useful newest first, everything else ascending
+        let order = query.order.unwrap_or(match sort {
+            GradesSortKey::Date => SortOrder::Desc,
+            _ => SortOrder::Asc,
+        });
+
+        grades.sort_by(|a, b| {
+            let ordering = match sort {
+                GradesSortKey::Date => {
+                    cmp_missing_last(a.newest_bekanntgabe(), b.newest_bekanntgabe(), order)
+                }
+                GradesSortKey::Name => {
+                    apply_order(a.name.to_lowercase().cmp(&b.name.to_lowercase()), order)
+                }
+                GradesSortKey::Grade => {
+                    cmp_missing_last(a.grade_value.numeric(), b.grade_value.numeric(), order)
+                }
+                GradesSortKey::Ects => apply_order(a.credit_points.cmp(&b.credit_points), order),
+            };
+            ordering.then_with(|| a.name.cmp(&b.name))
+        });
+    } else if query.order == Some(SortOrder::Asc) {
+        // parser order is newest first
+        grades.reverse();
+    }
+
+    grades
+}
+
+enum PeriodFilter {
+    Parsed(Option<AcademicPeriod>),
+    Raw(String),
+}
+
+fn apply_order(ordering: Ordering, order: SortOrder) -> Ordering {
+    match order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    }
+}
+
+// missing values (no date, not numerically graded) always go to the end
+fn cmp_missing_last<T: PartialOrd>(a: Option<T>, b: Option<T>, order: SortOrder) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => apply_order(a.partial_cmp(&b).unwrap_or(Ordering::Equal), order),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
@@ -0,0 +1,205 @@
+// Opt-in (GROUP_TIMETABLE_CACHE_TTL_SEC) cache of /room/json, shared within a seminar group.
+// A timetable is only handed to other members once two of them fetched exactly the same items,
+// and only to members whose own timetable for the same range matched it. Everyone else
+// (electives, old sessions without a seminar group) keeps fetching from CampusDual.
+
+use std::{
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use axum::Extension;
+use chrono::{NaiveTime, TimeZone};
+use chrono_tz::Europe::Berlin;
+use fnv::FnvHasher;
+use http::StatusCode;
+use lazy_static::lazy_static;
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    cache_stuff::TtlCache,
+    constants::{GROUP_TIMETABLE_CACHE_MAX_ENTRIES, GROUP_TIMETABLE_CACHE_TTL_SEC},
+    stundenplan::fetch_stundenplan,
+    types::{CdAuthData, ResponseError, StundenplanItem},
+};
+
+// members needed before a timetable is shared
+const MIN_CONFIRMATIONS: usize = 2;
+// different timetables kept per group and range (electives, mid-change)
+const MAX_VARIANTS: usize = 8;
+
+lazy_static! {
+    // "<group>/<start>/<end>"
+    static ref GROUP_TIMETABLES: TtlCache<GroupTimetable> = new_group_cache();
+    // "<group>/<start>/<end>/<user>": whether the user's timetable matched the group's one for
+    // that range, a match in one week says nothing about the next (electives)
+    static ref GROUP_MEMBERS: TtlCache<bool> = new_group_cache();
+}
+
+fn new_group_cache<V: Clone>() -> TtlCache<V> {
+    TtlCache::new(
+        Duration::from_secs(*GROUP_TIMETABLE_CACHE_TTL_SEC.get().unwrap()),
+        *GROUP_TIMETABLE_CACHE_MAX_ENTRIES.get().unwrap(),
+    )
+}
+
+#[derive(Clone, Default)]
+struct GroupTimetable {
+    variants: Vec<GroupTimetableVariant>,
+}
+
+#[derive(Clone)]
+struct GroupTimetableVariant {
+    fingerprint: u64,
+    items: Vec<StundenplanItem>,
+    members: Vec<String>,
+}
+
+impl GroupTimetable {
+    // the timetable most members got, once enough of them agree
+    fn confirmed(&self) -> Option<&GroupTimetableVariant> {
+        self.variants
+            .iter()
+            .filter(|variant| variant.members.len() >= MIN_CONFIRMATIONS)
+            .max_by_key(|variant| variant.members.len())
+    }
+}
+
+// fetch_stundenplan, but served from / added to the group cache where possible
+pub async fn fetch_group_stundenplan(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    start: i64,
+    end: i64,
+) -> Result<Vec<StundenplanItem>, ResponseError> {
+    let group = cd_auth_data.seminar_group.trim();
+    if !cache_enabled() || group.is_empty() || !is_day_range(start, end) {
+        return fetch_stundenplan(client, cd_auth_data, start, end).await;
+    }
+
+    let range_key = format!("{group}/{start}/{end}");
+    let member_key = format!("{range_key}/{}", cd_auth_data.user);
+    let member_matched = GROUP_MEMBERS.get(&member_key);
+
+    if member_matched == Some(true) {
+        if let Some(variant) = GROUP_TIMETABLES
+            .get(&range_key)
+            .as_ref()
+            .and_then(GroupTimetable::confirmed)
+        {
+            return Ok(variant.items.clone());
+        }
+    }
+
+    let items = fetch_stundenplan(client, cd_auth_data, start, end).await?;
+    record_fetch(&range_key, &cd_auth_data.user, member_matched, &items);
+
+    Ok(items)
+}
+
+fn record_fetch(
+    range_key: &str,
+    user: &str,
+    member_matched: Option<bool>,
+    items: &[StundenplanItem],
+) {
+    let fingerprint = items_fingerprint(items);
+    let mut timetable = GROUP_TIMETABLES.get(range_key).unwrap_or_default();
+    let confirmed_before = timetable.confirmed().map(|variant| variant.fingerprint);
+
+    // a member that used to match got something else: the group's timetable (or the member's
+    // electives) changed, start over for this range
+    if member_matched == Some(true) && confirmed_before.is_some_and(|before| before != fingerprint)
+    {
+        GROUP_TIMETABLES.remove(range_key);
+        let member_prefix = format!("{range_key}/");
+        GROUP_MEMBERS.remove_where(|key| key.starts_with(&member_prefix));
+        return;
+    }
+
+    for variant in &mut timetable.variants {
+        variant.members.retain(|member| member != user);
+    }
+    match timetable
+        .variants
+        .iter_mut()
+        .find(|variant| variant.fingerprint == fingerprint)
+    {
+        Some(variant) => variant.members.push(user.to_string()),
+        None => timetable.variants.push(GroupTimetableVariant {
+            fingerprint,
+            items: items.to_vec(),
+            members: vec![user.to_string()],
+        }),
+    }
+    timetable
+        .variants
+        .retain(|variant| !variant.members.is_empty());
+    timetable
+        .variants
+        .sort_by_key(|variant| std::cmp::Reverse(variant.members.len()));
+    timetable.variants.truncate(MAX_VARIANTS);
+
+    if let Some(confirmed) = timetable.confirmed() {
+        for variant in &timetable.variants {
+            let matched = variant.fingerprint == confirmed.fingerprint;
+            for member in &variant.members {
+                GROUP_MEMBERS.insert(format!("{range_key}/{member}"), matched);
+            }
+        }
+    }
+
+    GROUP_TIMETABLES.insert(range_key.to_string(), timetable);
+}
+
+// drops every cached range of the group along with the member flags, everyone has to match again
+pub fn invalidate_group(group: &str) {
+    let group = group.trim();
+    if !cache_enabled() || group.is_empty() {
+        return;
+    }
+    let prefix = format!("{group}/");
+    GROUP_TIMETABLES.remove_where(|key| key.starts_with(&prefix));
+    GROUP_MEMBERS.remove_where(|key| key.starts_with(&prefix));
+}
+
+pub async fn post_invalidate_timetable_cache(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+) -> Result<StatusCode, ResponseError> {
+    invalidate_group(&cd_auth_data.seminar_group);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn cache_enabled() -> bool {
+    *GROUP_TIMETABLE_CACHE_TTL_SEC.get().unwrap() > 0
+}
+
+// only the whole-day ranges of the timetable endpoints repeat, "from now" ranges never hit
+fn is_day_range(start: i64, end: i64) -> bool {
+    let is_midnight = |timestamp: i64| {
+        Berlin
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .is_some_and(|date_time| date_time.time() == NaiveTime::MIN)
+    };
+    start < end && is_midnight(start) && is_midnight(end)
+}
+
+// CampusDual's order is not guaranteed, so the items are hashed in a fixed order
+fn items_fingerprint(items: &[StundenplanItem]) -> u64 {
+    let mut item_hashes = items
+        .iter()
+        .map(|item| {
+            let mut hasher = FnvHasher::default();
+            serde_json::to_string(item)
+                .unwrap_or_default()
+                .hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect::<Vec<_>>();
+    item_hashes.sort_unstable();
+
+    let mut hasher = FnvHasher::default();
+    item_hashes.hash(&mut hasher);
+    hasher.finish()
+}
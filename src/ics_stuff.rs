@@ -0,0 +1,162 @@
+// Minimal iCalendar (RFC 5545) writer. All times are written in UTC, so no VTIMEZONE is needed.
+use std::hash::{Hash, Hasher};
+
+use chrono::{DateTime, TimeZone, Utc};
+use fnv::FnvHasher;
+
+pub const ICS_PRODID: &str = "-//campus-api//CampusDual//DE";
+
+#[derive(Debug, Clone)]
+pub struct IcsAlarm {
+    pub minutes_before: i64,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct IcsEvent {
+    pub uid: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub start: DateTime<Utc>,
+    // no end: zero length event, used for deadlines
+    pub end: Option<DateTime<Utc>>,
+    pub categories: Vec<String>,
+    pub alarms: Vec<IcsAlarm>,
+}
+
+impl IcsEvent {
+    pub fn new<Tz: TimeZone>(
+        uid: impl Into<String>,
+        summary: impl Into<String>,
+        start: &DateTime<Tz>,
+    ) -> Self {
+        IcsEvent {
+            uid: uid.into(),
+            summary: summary.into(),
+            description: None,
+            location: None,
+            start: start.with_timezone(&Utc),
+            end: None,
+            categories: Vec::new(),
+            alarms: Vec::new(),
+        }
+    }
+
+    pub fn render(&self, dtstamp: &DateTime<Utc>) -> String {
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", escape_text(&self.uid)),
+            format!("DTSTAMP:{}", format_utc(dtstamp)),
+            format!("DTSTART:{}", format_utc(&self.start)),
+        ];
+        if let Some(end) = &self.end {
+            lines.push(format!("DTEND:{}", format_utc(end)));
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&self.summary)));
+        if let Some(description) = &self.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        if let Some(location) = &self.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        if !self.categories.is_empty() {
+            lines.push(format!(
+                "CATEGORIES:{}",
+                self.categories
+                    .iter()
+                    .map(|category| escape_text(category))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ));
+        }
+        for alarm in &self.alarms {
+            lines.extend([
+                "BEGIN:VALARM".to_string(),
+                "ACTION:DISPLAY".to_string(),
+                format!("DESCRIPTION:{}", escape_text(&alarm.description)),
+                format!("TRIGGER:-PT{}M", alarm.minutes_before.max(0)),
+                "END:VALARM".to_string(),
+            ]);
+        }
+        lines.push("END:VEVENT".to_string());
+
+        lines.iter().map(|line| fold_line(line)).collect()
+    }
+
+    // changes whenever the event does, DTSTAMP is left out
+    pub fn etag(&self) -> String {
+        let mut hasher = FnvHasher::default();
+        self.render(&DateTime::UNIX_EPOCH).hash(&mut hasher);
+        format!("\"{:016x}\"", hasher.finish())
+    }
+}
+
+pub struct IcsCalendar {
+    pub name: String,
+    pub events: Vec<IcsEvent>,
+    // METHOD:PUBLISH, not allowed in CalDAV resources
+    pub publish: bool,
+}
+
+impl IcsCalendar {
+    pub fn new(name: impl Into<String>) -> Self {
+        IcsCalendar {
+            name: name.into(),
+            events: Vec::new(),
+            publish: true,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let dtstamp = Utc::now();
+
+        let mut ics = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            &format!("PRODID:{ICS_PRODID}"),
+            "CALSCALE:GREGORIAN",
+            if self.publish { "METHOD:PUBLISH" } else { "" },
+            &format!("X-WR-CALNAME:{}", escape_text(&self.name)),
+        ]
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| fold_line(line))
+        .collect::<String>();
+
+        for event in &self.events {
+            ics.push_str(&event.render(&dtstamp));
+        }
+        ics.push_str("END:VCALENDAR\r\n");
+
+        ics
+    }
+}
+
+fn format_utc(date_time: &DateTime<Utc>) -> String {
+    date_time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+// lines longer than 75 octets are folded with CRLF + space, never inside a UTF-8 sequence
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut line_len = 0;
+    for c in line.chars() {
+        if line_len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            line_len = 1;
+        }
+        folded.push(c);
+        line_len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
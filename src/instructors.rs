@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::Query, Extension, Json};
+use chrono::{Duration, TimeZone};
+use chrono_tz::Europe::Berlin;
+use futures::{future, stream, StreamExt};
+use http::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    campus_backend::req_client_funcs::{get_client_default, get_client_with_cd_cookie},
+    color_stuff::ColorScheme,
+    constants::BATCH_CONCURRENCY,
+    exam_calendar::{fetch_exam_listings, ExamListing},
+    exam_details::fetch_exam_offer,
+    services::fetch_reminders,
+    stundenplan::{fetch_timetable, split_names, stundenplan_range},
+    time_stuff::{berlin_now, parse_sap_date, parse_sap_time},
+    types::{
+        CdAuthData, CdExamDetails, Instructor, InstructorExam, InstructorModule, InstructorSession,
+        InstructorsQuery, ResponseError, StundenplanQuery, TimetableEvent, TimetableEventStatus,
+        UpcomingReminder,
+    },
+};
+
+const DEFAULT_DAYS: i64 = 90;
+const MAX_DAYS: i64 = 300;
+// modules taught in the last weeks still count, even without upcoming sessions
+const LOOKBACK_DAYS: i64 = 28;
+const MAX_LISTED_SESSIONS: usize = 5;
+
+pub async fn get_instructors(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Query(instructors_query): Query<InstructorsQuery>,
+) -> Result<Json<Vec<Instructor>>, ResponseError> {
+    let days = instructors_query.days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(ResponseError {
+            message: format!("days muss zwischen 1 und {MAX_DAYS} liegen"),
+            status_code: StatusCode::BAD_REQUEST,
+        });
+    }
+
+    let today = berlin_now().date_naive();
+    let (start, end) = stundenplan_range(&StundenplanQuery {
+        start: Some(today - Duration::days(LOOKBACK_DAYS)),
+        end: Some(today + Duration::days(days)),
+        ..Default::default()
+    })?;
+
+    let client = get_client_default(true)?;
+    let color_scheme = ColorScheme::default();
+    let (timetable, reminders, exams) = tokio::join!(
+        fetch_timetable(&client, &cd_auth_data, &start, &end, &color_scheme),
+        fetch_reminders(&client, &cd_auth_data),
+        fetch_exam_offers(&client, &cd_auth_data),
+    );
+
+    let mut directory = InstructorDirectory::default();
+    for event in &timetable? {
+        directory.add_timetable_event(event);
+    }
+
+    // like with the deadlines, the timetable is what matters
+    match reminders {
+        Ok(reminders) => {
+            for reminder in &reminders.upcoming {
+                directory.add_reminder(reminder);
+            }
+        }
+        Err(e) => log::warn!("Instructors: reminders unavailable: {}", e.message),
+    }
+    match exams {
+        Ok(exams) => {
+            for (listing, details) in &exams {
+                directory.add_exam(listing, details);
+            }
+        }
+        Err(e) => log::warn!("Instructors: exams unavailable: {}", e.message),
+    }
+
+    Ok(Json(directory.finish()))
+}
+
+// only exams with offer details name their instructor
+async fn fetch_exam_offers(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+) -> Result<Vec<(ExamListing, CdExamDetails)>, ResponseError> {
+    let cookie_client = get_client_with_cd_cookie(true, cd_auth_data.cookie.clone())?;
+    let listings = fetch_exam_listings(&cookie_client).await?;
+
+    let concurrency = (*BATCH_CONCURRENCY.get().unwrap()).max(1);
+    Ok(stream::iter(listings)
+        .map(|listing| async move {
+            let details = fetch_exam_offer(client, cd_auth_data, listing.metadata.as_ref()?)
+                .await
+                .inspect_err(|e| {
+                    log::warn!(
+                        "Instructors: no details for {}: {}",
+                        listing.name,
+                        e.message
+                    )
+                })
+                .ok()?;
+            Some((listing, details))
+        })
+        .buffer_unordered(concurrency)
+        .filter_map(future::ready)
+        .collect()
+        .await)
+}
+
+#[derive(Default)]
+struct InstructorDirectory {
+    // keyed by the normalized name, so the order is alphabetical
+    instructors: BTreeMap<String, Instructor>,
+}
+
+impl InstructorDirectory {
+    // short names are only paired up if both lists have the same length
+    fn instructors_mut(
+        &mut self,
+        names: Vec<String>,
+        short_names: Vec<String>,
+    ) -> Vec<&mut Instructor> {
+        let pairs = if names.is_empty() {
+            short_names.into_iter().map(|short| (short, None)).collect()
+        } else if names.len() == short_names.len() {
+            names
+                .into_iter()
+                .zip(short_names.into_iter().map(Some))
+                .collect()
+        } else {
+            names
+                .into_iter()
+                .map(|name| (name, None))
+                .collect::<Vec<_>>()
+        };
+
+        let mut keys = Vec::new();
+        for (name, short_name) in pairs {
+            let key = normalize_name(&name);
+            let instructor = self
+                .instructors
+                .entry(key.clone())
+                .or_insert_with(|| Instructor {
+                    name,
+                    short_names: Vec::new(),
+                    modules: Vec::new(),
+                    upcoming_session_count: 0,
+                    upcoming_sessions: Vec::new(),
+                    exams: Vec::new(),
+                });
+            if let Some(short_name) = short_name {
+                if !instructor.short_names.contains(&short_name) {
+                    instructor.short_names.push(short_name);
+                }
+            }
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        self.instructors
+            .iter_mut()
+            .filter(|(key, _)| keys.contains(key))
+            .map(|(_, instructor)| instructor)
+            .collect()
+    }
+
+    fn add_timetable_event(&mut self, event: &TimetableEvent) {
+        let module = InstructorModule {
+            code: event.module_code.clone(),
+            title: event.module_title.clone(),
+        };
+        // cancelled sessions still tell who teaches the module
+        let session = (event.start >= berlin_now()
+            && event.status != TimetableEventStatus::Cancelled)
+            .then(|| InstructorSession {
+                title: event.title.clone(),
+                start: event.start,
+                end: Some(event.end),
+                room: event.room.as_ref().map(|room| room.short.clone()),
+            });
+
+        for instructor in
+            self.instructors_mut(event.instructors.clone(), event.instructors_short.clone())
+        {
+            add_module(instructor, &module);
+            if let Some(session) = &session {
+                add_session(instructor, session);
+            }
+        }
+    }
+
+    fn add_reminder(&mut self, reminder: &UpcomingReminder) {
+        let title = [&reminder.sm_stext, &reminder.sm_short, &reminder.comment]
+            .into_iter()
+            .map(|text| text.trim())
+            .find(|text| !text.is_empty())
+            .unwrap_or_default()
+            .to_string();
+        let module = Some(reminder.sm_short.trim())
+            .filter(|code| !code.is_empty())
+            .map(|code| InstructorModule {
+                code: Some(code.to_string()),
+                title: title.clone(),
+            });
+
+        let Some(date) = parse_sap_date(&reminder.evdat) else {
+            return;
+        };
+        let at = |time: &str| {
+            Berlin
+                .from_local_datetime(&date.and_time(parse_sap_time(time)?))
+                .earliest()
+        };
+        let session = at(&reminder.beguz)
+            .filter(|start| *start >= berlin_now())
+            .map(|start| InstructorSession {
+                title,
+                start,
+                end: at(&reminder.enduz).filter(|end| *end > start),
+                room: Some(reminder.sroom.trim())
+                    .filter(|room| !room.is_empty())
+                    .map(|room| room.to_string()),
+            });
+
+        for instructor in self.instructors_mut(
+            split_names(&reminder.instructor),
+            split_names(&reminder.sinstructor),
+        ) {
+            if let Some(module) = &module {
+                add_module(instructor, module);
+            }
+            if let Some(session) = &session {
+                add_session(instructor, session);
+            }
+        }
+    }
+
+    fn add_exam(&mut self, listing: &ExamListing, details: &CdExamDetails) {
+        let exam = InstructorExam {
+            name: listing.name.clone(),
+            registered: listing.registered,
+            date: parse_sap_date(&details.ev_examdate).or(listing.date),
+            start: parse_sap_time(&details.ev_exambegtime).or(listing.start),
+            internal_metadata: listing.metadata.clone(),
+        };
+
+        for instructor in self.instructors_mut(split_names(&details.ev_instructor), Vec::new()) {
+            if !instructor
+                .exams
+                .iter()
+                .any(|other| other.internal_metadata == exam.internal_metadata)
+            {
+                instructor.exams.push(exam.clone());
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<Instructor> {
+        self.instructors
+            .into_values()
+            .map(|mut instructor| {
+                instructor
+                    .upcoming_sessions
+                    .sort_by_key(|session| session.start);
+                instructor.upcoming_session_count = instructor.upcoming_sessions.len();
+                instructor.upcoming_sessions.truncate(MAX_LISTED_SESSIONS);
+                instructor.exams.sort_by_key(|exam| (exam.date, exam.start));
+                instructor
+            })
+            .collect()
+    }
+}
+
+// "Müller,  Hans" and "müller, hans" are the same person
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn add_module(instructor: &mut Instructor, module: &InstructorModule) {
+    let known = instructor
+        .modules
+        .iter()
+        .any(|other| match (&other.code, &module.code) {
+            (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+            _ => other.title == module.title,
+        });
+    if !known {
+        instructor.modules.push(module.clone());
+    }
+}
+
+// the reminders repeat sessions that are in the timetable as well, under a different title, so
+// one instructor at the same time in the same (or an unknown) room is the same session
+fn add_session(instructor: &mut Instructor, session: &InstructorSession) {
+    let same_room = |other: &InstructorSession| match (&other.room, &session.room) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        _ => true,
+    };
+    if !instructor
+        .upcoming_sessions
+        .iter()
+        .any(|other| other.start == session.start && same_room(other))
+    {
+        instructor.upcoming_sessions.push(session.clone());
+    }
+}
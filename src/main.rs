@@ -7,13 +7,38 @@ use constants::{
 use tokio::net::TcpListener;
 
 mod auth;
+mod cache_stuff;
+mod caldav;
 pub mod campus_backend;
 mod color_stuff;
 mod constants;
+mod deadlines;
 mod encryption;
+mod exam_actions;
+mod exam_calendar;
+mod exam_conflicts;
+mod exam_details;
+mod exam_scheduler;
+mod file_stuff;
+mod grade_export;
+mod grade_filter;
+mod group_timetable_cache;
+mod ics_stuff;
+mod instructors;
+mod pdf_stuff;
+mod png_stuff;
 mod ratelimit_keyextractor;
+mod room_directory;
 mod routes;
 mod services;
+mod stundenplan;
+mod time_stuff;
+mod timetable_analysis;
+mod timetable_changes;
+mod timetable_exemptions;
+mod timetable_export;
+mod timetable_filter;
+mod timetable_watch;
 mod types;
 
 #[tokio::main]
@@ -41,6 +66,11 @@ async fn main() {
     let cert = reqwest::Certificate::from_pem(buf).unwrap();
     CD_CERT_PEM.set(cert).unwrap();
 
+    exam_scheduler::start().await;
+    timetable_watch::start().await;
+    timetable_exemptions::start().await;
+    room_directory::start();
+
     let listener = TcpListener::bind("0.0.0.0:8080")
         .await
         .expect("Unable to start the server");
@@ -0,0 +1,215 @@
+// Minimal PDF writer: standard Type1 fonts (no embedding), filled rectangles, lines and text.
+// Coordinates are given in points from the top left corner of the page.
+
+pub const A4_WIDTH: f32 = 595.0;
+pub const A4_HEIGHT: f32 = 842.0;
+
+#[derive(Clone, Copy)]
+pub enum PdfFont {
+    Regular,
+    Bold,
+}
+
+impl PdfFont {
+    fn resource_name(&self) -> &'static str {
+        match self {
+            PdfFont::Regular => "F1",
+            PdfFont::Bold => "F2",
+        }
+    }
+}
+
+pub struct PdfPage {
+    width: f32,
+    height: f32,
+    content: String,
+}
+
+impl PdfPage {
+    pub fn new(width: f32, height: f32) -> Self {
+        PdfPage {
+            width,
+            height,
+            content: String::new(),
+        }
+    }
+
+    pub fn text(
+        &mut self,
+        x: f32,
+        y: f32,
+        size: f32,
+        font: PdfFont,
+        rgb: (u8, u8, u8),
+        text: &str,
+    ) {
+        let (r, g, b) = rgb_to_pdf(rgb);
+        self.content.push_str(&format!(
+            "BT {r:.3} {g:.3} {b:.3} rg /{} {size:.1} Tf {x:.2} {:.2} Td ({}) Tj ET\n",
+            font.resource_name(),
+            self.height - y,
+            escape_pdf_string(text)
+        ));
+    }
+
+    pub fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, rgb: (u8, u8, u8)) {
+        let (r, g, b) = rgb_to_pdf(rgb);
+        self.content.push_str(&format!(
+            "{r:.3} {g:.3} {b:.3} rg {x:.2} {:.2} {w:.2} {h:.2} re f\n",
+            self.height - y - h
+        ));
+    }
+
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32, rgb: (u8, u8, u8)) {
+        let (r, g, b) = rgb_to_pdf(rgb);
+        self.content.push_str(&format!(
+            "{r:.3} {g:.3} {b:.3} RG {width:.2} w {x1:.2} {:.2} m {x2:.2} {:.2} l S\n",
+            self.height - y1,
+            self.height - y2
+        ));
+    }
+}
+
+#[derive(Default)]
+pub struct PdfDocument {
+    pages: Vec<PdfPage>,
+}
+
+impl PdfDocument {
+    pub fn add_page(&mut self, page: PdfPage) {
+        self.pages.push(page);
+    }
+
+    pub fn render(&self) -> Vec<u8> {
+        // fixed objects: 1 catalog, 2 page tree, 3 + 4 fonts, then (page, content) per page
+        let mut objects: Vec<Vec<u8>> = Vec::new();
+
+        let kids = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", 5 + i * 2))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(
+            format!(
+                "<< /Type /Pages /Kids [{kids}] /Count {} >>",
+                self.pages.len()
+            )
+            .into_bytes(),
+        );
+        objects.push(
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+        );
+        objects.push(
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+        );
+
+        for (i, page) in self.pages.iter().enumerate() {
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                    page.width,
+                    page.height,
+                    6 + i * 2
+                )
+                .into_bytes(),
+            );
+
+            let content = encode_win_ansi(&page.content);
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(&content);
+            stream.extend_from_slice(b"\nendstream");
+            objects.push(stream);
+        }
+
+        let mut out: Vec<u8> = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref_offset = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n", objects.len() + 1).as_bytes());
+        out.extend_from_slice(b"0000000000 65535 f \n");
+        for offset in offsets {
+            out.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .as_bytes(),
+        );
+
+        out
+    }
+}
+
+// Rough Helvetica text width, good enough for truncating table cells
+pub fn approx_text_width(text: &str, size: f32) -> f32 {
+    text.chars()
+        .map(|c| match c {
+            'i' | 'j' | 'l' | '.' | ',' | ':' | ';' | '\'' | '|' | '!' => 0.25,
+            'f' | 't' | 'r' | ' ' | '(' | ')' | '-' | '/' => 0.33,
+            'm' | 'w' | 'M' | 'W' => 0.85,
+            c if c.is_uppercase() => 0.68,
+            _ => 0.55,
+        })
+        .sum::<f32>()
+        * size
+}
+
+pub fn truncate_to_width(text: &str, size: f32, max_width: f32) -> String {
+    if approx_text_width(text, size) <= max_width {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    for c in text.chars() {
+        truncated.push(c);
+        if approx_text_width(&truncated, size) + approx_text_width("...", size) > max_width {
+            truncated.pop();
+            break;
+        }
+    }
+    truncated + "..."
+}
+
+fn rgb_to_pdf((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+}
+
+fn escape_pdf_string(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+        .replace(['\r', '\n'], " ")
+}
+
+// content streams are built as UTF-8 strings, the standard fonts expect WinAnsi bytes
+fn encode_win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            '\u{20}'..='\u{7E}' | '\n' => c as u8,
+            '\u{A0}'..='\u{FF}' => c as u32 as u8,
+            '€' => 0x80,
+            '‚' => 0x82,
+            '„' => 0x84,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            _ => b'?',
+        })
+        .collect()
+}
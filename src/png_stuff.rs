@@ -0,0 +1,212 @@
+// Minimal raster canvas for PNG output: filled rectangles, lines and text in a built-in 5x8
+// bitmap font (ASCII + German umlauts), so no font files are needed.
+// Coordinates are given in pixels from the top left corner, text y is the baseline.
+
+use anyhow::Result;
+
+// column-major glyphs, bit n of a column is pixel row n (top = 0, row 7 is for descenders)
+const FONT_ASCII: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x56, 0x20, 0x50], // &
+    [0x00, 0x08, 0x07, 0x03, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x2A, 0x1C, 0x7F, 0x1C, 0x2A], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x80, 0x70, 0x30, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x00, 0x60, 0x60, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x72, 0x49, 0x49, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x49, 0x4D, 0x33], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x31], // 6
+    [0x41, 0x21, 0x11, 0x09, 0x07], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x46, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x00, 0x14, 0x00, 0x00], // :
+    [0x00, 0x40, 0x34, 0x00, 0x00], // ;
+    [0x00, 0x08, 0x14, 0x22, 0x41], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x59, 0x09, 0x06], // ?
+    [0x3E, 0x41, 0x5D, 0x59, 0x4E], // @
+    [0x7C, 0x12, 0x11, 0x12, 0x7C], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x41, 0x3E], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x41, 0x51, 0x73], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x1C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x26, 0x49, 0x49, 0x49, 0x32], // S
+    [0x03, 0x01, 0x7F, 0x01, 0x03], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x59, 0x49, 0x4D, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x41], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x41, 0x7F], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x03, 0x07, 0x08, 0x00], // `
+    [0x20, 0x54, 0x54, 0x78, 0x40], // a
+    [0x7F, 0x28, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x28], // c
+    [0x38, 0x44, 0x44, 0x28, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x00, 0x08, 0x7E, 0x09, 0x02], // f
+    [0x18, 0xA4, 0xA4, 0x9C, 0x78], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x40, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x78, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0xFC, 0x18, 0x24, 0x24, 0x18], // p
+    [0x18, 0x24, 0x24, 0x18, 0xFC], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x24], // s
+    [0x04, 0x04, 0x3F, 0x44, 0x24], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x4C, 0x90, 0x90, 0x90, 0x7C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x77, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x02, 0x01, 0x02, 0x04, 0x02], // ~
+];
+
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        ' '..='~' => FONT_ASCII[c as usize - ' ' as usize],
+        'Ä' => [0x7D, 0x12, 0x11, 0x12, 0x7D],
+        'Ö' => [0x39, 0x44, 0x44, 0x44, 0x39],
+        'Ü' => [0x3D, 0x40, 0x40, 0x40, 0x3D],
+        'ä' => [0x20, 0x55, 0x54, 0x79, 0x40],
+        'ö' => [0x38, 0x45, 0x44, 0x45, 0x38],
+        'ü' => [0x3C, 0x41, 0x40, 0x21, 0x7C],
+        'ß' => [0x7E, 0x01, 0x4D, 0x52, 0x20],
+        '–' | '—' => FONT_ASCII['-' as usize - ' ' as usize],
+        _ => FONT_ASCII['?' as usize - ' ' as usize],
+    }
+}
+
+// glyph cell including one column / row of spacing
+const GLYPH_WIDTH: u32 = 6;
+const GLYPH_HEIGHT: u32 = 8;
+const GLYPH_BASELINE: u32 = 7;
+
+pub struct PngCanvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl PngCanvas {
+    pub fn new(width: u32, height: u32, background: (u8, u8, u8)) -> Self {
+        let (r, g, b) = background;
+        PngCanvas {
+            width,
+            height,
+            pixels: [r, g, b].repeat((width * height) as usize),
+        }
+    }
+
+    fn set_pixel(&mut self, x: i64, y: i64, (r, g, b): (u8, u8, u8)) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let offset = (y as usize * self.width as usize + x as usize) * 3;
+        self.pixels[offset..offset + 3].copy_from_slice(&[r, g, b]);
+    }
+
+    pub fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, rgb: (u8, u8, u8)) {
+        let (x1, y1) = (x.round() as i64, y.round() as i64);
+        let (x2, y2) = ((x + w).round() as i64, (y + h).round() as i64);
+        for py in y1.max(0)..y2.min(self.height as i64) {
+            for px in x1.max(0)..x2.min(self.width as i64) {
+                self.set_pixel(px, py, rgb);
+            }
+        }
+    }
+
+    // only horizontal and vertical lines are needed
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32, rgb: (u8, u8, u8)) {
+        let width = width.max(1.0);
+        if (y1 - y2).abs() < f32::EPSILON {
+            self.fill_rect(x1.min(x2), y1 - width / 2.0, (x2 - x1).abs(), width, rgb);
+        } else {
+            self.fill_rect(x1 - width / 2.0, y1.min(y2), width, (y2 - y1).abs(), rgb);
+        }
+    }
+
+    fn scale(size: f32) -> u32 {
+        ((size / GLYPH_HEIGHT as f32).round() as u32).max(1)
+    }
+
+    pub fn text_width(text: &str, size: f32) -> f32 {
+        (text.chars().count() as u32 * GLYPH_WIDTH * Self::scale(size)) as f32
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, rgb: (u8, u8, u8), text: &str) {
+        let scale = Self::scale(size) as i64;
+        let top = y.round() as i64 - GLYPH_BASELINE as i64 * scale;
+        let mut left = x.round() as i64;
+
+        for c in text.chars() {
+            for (column, bits) in glyph(c).iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT as i64 {
+                    if bits & (1 << row) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale + bold as i64 {
+                            self.set_pixel(
+                                left + column as i64 * scale + dx,
+                                top + row * scale + dy,
+                                rgb,
+                            );
+                        }
+                    }
+                }
+            }
+            left += GLYPH_WIDTH as i64 * scale;
+        }
+    }
+
+    pub fn render(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(out)
+    }
+}
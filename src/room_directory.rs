@@ -0,0 +1,187 @@
+// Optional (ROOM_DIRECTORY_FILE) mapping of CampusDual's room codes to buildings, floors and
+// coordinates. The file is read once at startup, the directory is the same for every user.
+
+use std::{collections::HashMap, fs};
+
+use axum::{extract::Query, Json};
+use http::StatusCode;
+use lazy_static::lazy_static;
+
+use crate::{
+    constants::ROOM_DIRECTORY_FILE,
+    types::{BuildingInfo, ResponseError, RoomDirectoryData, RoomInfo, RoomLocation, RoomsQuery},
+};
+
+struct RoomDirectory {
+    data: RoomDirectoryData,
+    // normalized code/alias -> index into data.rooms
+    rooms: HashMap<String, usize>,
+    // normalized building code -> index into data.buildings
+    buildings: HashMap<String, usize>,
+}
+
+lazy_static! {
+    static ref DIRECTORY: Option<RoomDirectory> = load_directory();
+}
+
+// loads the directory right away, so a broken file shows up in the log on startup
+pub fn start() {
+    if let Some(directory) = DIRECTORY.as_ref() {
+        log::info!(
+            "Room directory: {} room(s) and {} building(s) loaded",
+            directory.data.rooms.len(),
+            directory.data.buildings.len()
+        );
+    }
+}
+
+fn load_directory() -> Option<RoomDirectory> {
+    let file = ROOM_DIRECTORY_FILE.get()?.as_deref()?;
+
+    let content = fs::read_to_string(file)
+        .map_err(|e| log::error!("Room directory: unable to read {file}: {e}"))
+        .ok()?;
+    let data: RoomDirectoryData = if file.to_lowercase().ends_with(".toml") {
+        toml::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+    .map_err(|e| log::error!("Room directory: unable to parse {file}: {e}"))
+    .ok()?;
+
+    let mut rooms = HashMap::new();
+    for (i, room) in data.rooms.iter().enumerate() {
+        for code in std::iter::once(&room.code).chain(&room.aliases) {
+            if rooms.insert(normalize_code(code), i).is_some() {
+                log::warn!("Room directory: {code} is listed more than once");
+            }
+        }
+    }
+    let buildings = data
+        .buildings
+        .iter()
+        .enumerate()
+        .map(|(i, building)| (normalize_code(&building.code), i))
+        .collect();
+
+    Some(RoomDirectory {
+        data,
+        rooms,
+        buildings,
+    })
+}
+
+// "a 1.23" and "A1.23" are the same room
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+// same split as ExamRoom::parse
+fn building_code(room_code: &str) -> Option<&str> {
+    room_code
+        .trim()
+        .split_once([' ', '-', '.'])
+        .map(|(building, _)| building.trim())
+        .filter(|building| !building.is_empty())
+}
+
+impl RoomDirectory {
+    fn building(&self, code: &str) -> Option<&BuildingInfo> {
+        self.buildings
+            .get(&normalize_code(code))
+            .map(|&i| &self.data.buildings[i])
+    }
+
+    fn location(&self, raw: &str) -> Option<RoomLocation> {
+        match self.rooms.get(&normalize_code(raw)) {
+            Some(&i) => {
+                let room = &self.data.rooms[i];
+                let building = room
+                    .building
+                    .as_deref()
+                    .or_else(|| building_code(&room.code))
+                    .and_then(|code| self.building(code))
+                    .cloned();
+                Some(RoomLocation {
+                    code: Some(room.code.clone()),
+                    name: room.name.clone(),
+                    floor: room.floor,
+                    latitude: room.latitude.or(building.as_ref().and_then(|b| b.latitude)),
+                    longitude: room
+                        .longitude
+                        .or(building.as_ref().and_then(|b| b.longitude)),
+                    building,
+                })
+            }
+            None => {
+                let building = self.building(building_code(raw)?)?.clone();
+                Some(RoomLocation {
+                    code: None,
+                    name: None,
+                    floor: None,
+                    latitude: building.latitude,
+                    longitude: building.longitude,
+                    building: Some(building),
+                })
+            }
+        }
+    }
+}
+
+// None without a directory or for unknown rooms of unknown buildings
+pub fn room_location(raw: &str) -> Option<RoomLocation> {
+    DIRECTORY.as_ref()?.location(raw)
+}
+
+pub async fn get_rooms(
+    Query(rooms_query): Query<RoomsQuery>,
+) -> Result<Json<RoomDirectoryData>, ResponseError> {
+    let directory = DIRECTORY.as_ref().ok_or(ResponseError {
+        message: "Room directory is not enabled on this server".to_string(),
+        status_code: StatusCode::NOT_IMPLEMENTED,
+    })?;
+
+    let search = rooms_query
+        .search
+        .map(|search| search.trim().to_lowercase())
+        .filter(|search| !search.is_empty());
+    let Some(search) = search else {
+        return Ok(Json(RoomDirectoryData {
+            buildings: directory.data.buildings.clone(),
+            rooms: directory.data.rooms.clone(),
+        }));
+    };
+
+    let rooms: Vec<RoomInfo> = directory
+        .data
+        .rooms
+        .iter()
+        .filter(|room| {
+            std::iter::once(&room.code)
+                .chain(&room.aliases)
+                .chain(&room.name)
+                .any(|text| text.to_lowercase().contains(&search))
+        })
+        .cloned()
+        .collect();
+    // only the buildings of the rooms found
+    let buildings = directory
+        .data
+        .buildings
+        .iter()
+        .filter(|building| {
+            rooms.iter().any(|room| {
+                room.building
+                    .as_deref()
+                    .or_else(|| building_code(&room.code))
+                    .is_some_and(|code| normalize_code(code) == normalize_code(&building.code))
+            })
+        })
+        .cloned()
+        .collect();
+
+    Ok(Json(RoomDirectoryData { buildings, rooms }))
+}
@@ -3,21 +3,24 @@ use std::{sync::Arc, time::Duration};
 use axum::{
     middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{any, get, post},
     Router,
 };
-use http::{header::CONTENT_TYPE, Method};
+use http::{header::CONTENT_TYPE, HeaderName, Method};
 use tower_governor::{governor::GovernorConfigBuilder, GovernorLayer};
 use tower_http::cors::{Any, CorsLayer};
 
 use crate::{
-    auth,
+    auth, caldav,
     constants::{
-        LOGIN_RATELIMIT_QUOTA, LOGIN_RATELIMIT_RESTORE_INTERVAL_SEC, RATELIMIT_QUOTA,
+        BATCH_TOTAL_HEADER, LOGIN_RATELIMIT_QUOTA, LOGIN_RATELIMIT_RESTORE_INTERVAL_SEC,
+        PARSER_WARNINGS_HEADER, PARSER_WARNING_COUNT_HEADER, RATELIMIT_QUOTA,
         RATELIMIT_RESTORE_INTERVAL_SEC,
     },
+    deadlines, exam_calendar, exam_conflicts, exam_scheduler, group_timetable_cache, instructors,
     ratelimit_keyextractor::{GovIpOrGlobalExtractorHashed, GovJwtExtractorHashed},
-    services,
+    room_directory, services, stundenplan, timetable_analysis, timetable_changes,
+    timetable_exemptions, timetable_export, timetable_watch,
 };
 
 pub async fn app() -> Router {
@@ -43,8 +46,19 @@ pub async fn app() -> Router {
             .unwrap(),
     );
 
+    // CalDAV clients authenticate every request with Basic auth, so they are limited per IP
+    let governor_conf_caldav = Arc::new(
+        GovernorConfigBuilder::default()
+            .burst_size(*RATELIMIT_QUOTA.get().unwrap())
+            .per_second(*RATELIMIT_RESTORE_INTERVAL_SEC.get().unwrap())
+            .key_extractor(GovIpOrGlobalExtractorHashed)
+            .finish()
+            .unwrap(),
+    );
+
     let governor_limiter_jwt = governor_conf_jwt.limiter().clone();
     let governor_limiter_signin = governor_conf_signin.limiter().clone();
+    let governor_limiter_caldav = governor_conf_caldav.limiter().clone();
 
     // a separate background task to clean up
     let interval = Duration::from_secs(60);
@@ -52,6 +66,7 @@ pub async fn app() -> Router {
         std::thread::sleep(interval);
         governor_limiter_jwt.retain_recent();
         governor_limiter_signin.retain_recent();
+        governor_limiter_caldav.retain_recent();
     });
 
     let cors = CorsLayer::new()
@@ -59,23 +74,84 @@ pub async fn app() -> Router {
         .allow_methods([Method::GET, Method::POST])
         // allow requests from any origin
         .allow_origin(Any)
-        .allow_headers([CONTENT_TYPE]);
+        .allow_headers([CONTENT_TYPE])
+        .expose_headers([
+            HeaderName::from_static(PARSER_WARNINGS_HEADER),
+            HeaderName::from_static(PARSER_WARNING_COUNT_HEADER),
+            HeaderName::from_static(BATCH_TOTAL_HEADER),
+        ]);
 
     Router::new()
         .route("/check_revive_session", get(services::check_revive_session))
         .route("/get_grades", get(services::get_grades))
+        .route("/export_grades", get(services::get_grades_export))
         .route("/get_gradestats", post(services::get_gradestats))
+        .route(
+            "/get_gradestats_batch",
+            post(services::get_gradestats_batch),
+        )
         .route("/get_examsignup", get(services::get_examsignup))
+        .route("/get_examconflicts", get(exam_conflicts::get_examconflicts))
+        .route("/get_examcalendar", get(exam_calendar::get_exam_calendar))
         .route("/registerexam", post(services::post_registerexam))
         .route("/get_examdetails", post(services::get_examdetails))
+        .route(
+            "/get_examdetails_batch",
+            post(services::get_examdetails_batch),
+        )
         .route("/cancelexam", post(services::post_cancelexam))
+        .route("/registerexam_bulk", post(services::post_registerexam_bulk))
+        .route("/cancelexam_bulk", post(services::post_cancelexam_bulk))
+        .route(
+            "/schedule_registerexam",
+            post(exam_scheduler::post_schedule_registerexam),
+        )
+        .route("/scheduled_exams", get(exam_scheduler::get_scheduled_exams))
+        .route(
+            "/cancel_scheduled_exam",
+            post(exam_scheduler::post_cancel_scheduled_exam),
+        )
         .route("/get_examverfahren", get(services::get_examverfahren))
         .route("/get_ects", get(services::get_ects))
         .route("/get_fachsem", get(services::get_fachsem))
         .route("/get_examstats", get(services::get_examstats))
         .route("/get_stundenplan", get(services::get_stundenplan))
+        .route("/get_timetable", get(stundenplan::get_timetable))
+        .route(
+            "/get_timetable_analysis",
+            get(timetable_analysis::get_timetable_analysis),
+        )
+        .route(
+            "/get_timetable_export",
+            get(timetable_export::get_timetable_export),
+        )
+        .route(
+            "/invalidate_timetable_cache",
+            post(group_timetable_cache::post_invalidate_timetable_cache),
+        )
+        .route(
+            "/timetable_changes",
+            post(timetable_changes::post_timetable_changes),
+        )
+        .route(
+            "/timetable_exemptions",
+            get(timetable_exemptions::get_timetable_exemptions)
+                .post(timetable_exemptions::post_timetable_exemptions),
+        )
+        .route(
+            "/timetable_watch",
+            get(timetable_watch::get_timetable_watch).post(timetable_watch::post_timetable_watch),
+        )
+        .route(
+            "/delete_timetable_watch",
+            post(timetable_watch::post_delete_timetable_watch),
+        )
         .route("/get_reminders", get(services::get_reminders))
         .route("/get_timeline", get(services::get_timeline))
+        .route("/deadlines", get(deadlines::get_deadlines))
+        .route("/get_instructors", get(instructors::get_instructors))
+        .route("/rooms", get(room_directory::get_rooms))
+        .route("/caldav_password", post(caldav::post_caldav_password))
         // apply auth and jwt rate limiting to all previous (jwt is only stored as hash)
         .layer(GovernorLayer {
             config: governor_conf_jwt,
@@ -90,4 +166,15 @@ pub async fn app() -> Router {
         )
         .route("/", get(|| async { "API is reachable".into_response() }))
         .layer(cors)
+        // CalDAV has its own (Basic) auth and is not meant for browsers, so no CORS either
+        .merge(
+            Router::new()
+                .route("/.well-known/caldav", any(caldav::get_well_known_caldav))
+                .route("/caldav", any(caldav::caldav))
+                .route("/caldav/", any(caldav::caldav))
+                .route("/caldav/*path", any(caldav::caldav))
+                .layer(GovernorLayer {
+                    config: governor_conf_caldav,
+                }),
+        )
 }
@@ -1,35 +1,123 @@
-use axum::{Extension, Json};
+use axum::{
+    body::Bytes,
+    extract::Query,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
 use chrono::DateTime;
-use fnv::FnvHasher;
-use http::StatusCode;
-use std::{
-    hash::{Hash, Hasher},
-    time::Instant,
+use futures::{stream, StreamExt};
+use http::{
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    HeaderMap, HeaderValue, StatusCode,
 };
+use reqwest_middleware::ClientWithMiddleware;
+use std::{collections::HashMap, time::Instant};
 
 use crate::{
     auth::sign_in,
-    campus_backend::req_client_funcs::{
-        extract_exam_signup_options, extract_exam_verfahren_options, extract_grades,
-        get_client_default, get_client_with_cd_cookie,
+    campus_backend::{
+        login::get_userinfo_with_cd_cookie,
+        req_client_funcs::{extract_grades, get_client_default, get_client_with_cd_cookie},
+    },
+    color_stuff::ColorScheme,
+    constants::{
+        BATCH_CONCURRENCY, BATCH_TOTAL_HEADER, PARSER_WARNINGS_HEADER, PARSER_WARNING_COUNT_HEADER,
+    },
+    exam_actions::{
+        bulk_exam_action, cancel_exam, fetch_exam_signup_options, fetch_exam_verfahren_options,
+        register_exam, BulkExamActionItem, BulkExamActionRequest, ExamActionError, ExamActionKind,
+        ExamActionResult,
     },
-    color_stuff::hex_to_luminance,
+    exam_details::{fetch_exam_details_with_longtext, offer_key},
+    grade_export::{grades_to_csv, grades_to_jsonld, grades_to_pdf},
+    grade_filter::apply_grades_query,
+    group_timetable_cache::fetch_group_stundenplan,
+    stundenplan::{module_key, stundenplan_range, timetable_colors},
     types::{
         CampusDualGrade, CampusDualSignupOption, CampusDualVerfahrenOption, CampusLoginData,
         CampusReminders, CampusTimeline, CampusTimelineEvent, CdAuthData, CdExamDetails,
-        CdExamStats, CdGradeStatEntry, ExamRegistrationMetadata, ExportTimelineEvent,
-        ExportTimelineEvents, GradeStatsAllStudents, LoginResponse, ResponseError, StundenplanItem,
-        SubGradeMetadata,
+        CdExamStats, CdGradeStatEntry, ExamDetailsBatchEntry, ExamRegistrationMetadata,
+        ExportTimelineEvent, ExportTimelineEvents, GradeStatsAllStudents, GradeStatsBatchEntry,
+        GradesExportFormat, GradesExportQuery, GradesExtraction, GradesQuery, LoginResponse,
+        ParserWarning, ResponseError, StundenplanItem, StundenplanQuery, SubGradeMetadata,
     },
 };
 
+const MAX_BATCH_ITEMS: usize = 50;
+// keeps the warnings header well below the usual 8 KB proxy limits
+const MAX_HEADER_WARNINGS: usize = 10;
+const MAX_WARNING_FIELD_CHARS: usize = 100;
+
 pub async fn get_grades(
     Extension(cd_auth_data): Extension<CdAuthData>,
-) -> Result<Json<Vec<CampusDualGrade>>, ResponseError> {
+    Query(grades_query): Query<GradesQuery>,
+) -> Result<(HeaderMap, Json<Vec<CampusDualGrade>>), ResponseError> {
     let now = Instant::now();
     let client = get_client_with_cd_cookie(true, cd_auth_data.cookie)?;
     println!("Time to get client: {:.2?}", now.elapsed());
 
+    let extraction = fetch_grades(&client).await?;
+
+    Ok((
+        parser_warnings_header(&extraction.warnings)?,
+        Json(apply_grades_query(extraction.grades, &grades_query)),
+    ))
+}
+
+// Warnings of the (partial) parse are passed along as JSON in the X-Parser-Warnings header,
+// so the body stays a plain list for older clients. The header only holds the first few
+// warnings, X-Parser-Warning-Count tells how many there were.
+fn parser_warnings_header(warnings: &[ParserWarning]) -> Result<HeaderMap, ResponseError> {
+    let mut headers = HeaderMap::new();
+    if warnings.is_empty() {
+        return Ok(headers);
+    }
+
+    let truncate = |text: &str| {
+        text.chars()
+            .take(MAX_WARNING_FIELD_CHARS)
+            .collect::<String>()
+    };
+    let shown = warnings
+        .iter()
+        .take(MAX_HEADER_WARNINGS)
+        .map(|warning| ParserWarning {
+            row_id: warning.row_id.as_deref().map(truncate),
+            field: truncate(&warning.field),
+            reason: truncate(&warning.reason),
+        })
+        .collect::<Vec<_>>();
+
+    headers.insert(
+        PARSER_WARNING_COUNT_HEADER,
+        HeaderValue::from(warnings.len()),
+    );
+    headers.insert(
+        PARSER_WARNINGS_HEADER,
+        HeaderValue::from_str(&ascii_json(&serde_json::to_string(&shown)?))
+            .map_err(anyhow::Error::from)?,
+    );
+
+    Ok(headers)
+}
+
+// Header values are read as Latin-1 by browsers, so everything outside of printable ASCII
+// (only possible inside JSON strings) is sent as \u escape
+fn ascii_json(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() && !c.is_ascii_control() {
+            escaped.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                escaped.push_str(&format!("\\u{unit:04x}"));
+            }
+        }
+    }
+    escaped
+}
+
+async fn fetch_grades(client: &ClientWithMiddleware) -> Result<GradesExtraction, ResponseError> {
     let now = Instant::now();
 
     let grade_html = client
@@ -43,10 +131,55 @@ pub async fn get_grades(
 
     let now = Instant::now();
 
-    let grades = extract_grades(grade_html)?;
+    let extraction = extract_grades(grade_html)?;
     println!("extract grades: {:.2?}", now.elapsed());
 
-    Ok(Json(grades))
+    Ok(extraction)
+}
+
+pub async fn get_grades_export(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Query(export_query): Query<GradesExportQuery>,
+) -> Result<Response, ResponseError> {
+    let client = get_client_with_cd_cookie(true, cd_auth_data.cookie)?;
+
+    let grades = fetch_grades(&client).await?.grades;
+
+    let (content_type, extension, body) = match export_query.format {
+        GradesExportFormat::Csv => (
+            "text/csv; charset=utf-8",
+            "csv",
+            grades_to_csv(&grades).into_bytes(),
+        ),
+        GradesExportFormat::Jsonld => {
+            let user_basic_info = get_userinfo_with_cd_cookie(&client).await?;
+            (
+                "application/ld+json",
+                "jsonld",
+                serde_json::to_vec_pretty(&grades_to_jsonld(&grades, &user_basic_info))?,
+            )
+        }
+        GradesExportFormat::Pdf => {
+            let user_basic_info = get_userinfo_with_cd_cookie(&client).await?;
+            (
+                "application/pdf",
+                "pdf",
+                grades_to_pdf(&grades, &user_basic_info),
+            )
+        }
+    };
+
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"notenuebersicht.{extension}\""),
+            ),
+        ],
+        body,
+    )
+        .into_response())
 }
 
 pub async fn get_gradestats(
@@ -55,6 +188,87 @@ pub async fn get_gradestats(
 ) -> Result<Json<GradeStatsAllStudents>, ResponseError> {
     let client = get_client_with_cd_cookie(true, cd_auth_data.cookie)?;
 
+    Ok(Json(fetch_gradestats(&client, &subgrade_meta).await?))
+}
+
+pub async fn get_gradestats_batch(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    body: Bytes,
+) -> Result<(HeaderMap, Json<HashMap<String, GradeStatsBatchEntry>>), ResponseError> {
+    // only a missing (or empty) body means "all", anything else has to be a valid list
+    let subgrade_metas = if body.iter().all(u8::is_ascii_whitespace) {
+        None
+    } else {
+        let metas: Vec<SubGradeMetadata> =
+            serde_json::from_slice(&body).map_err(|e| ResponseError {
+                message: format!("Ungültige Liste: {e}"),
+                status_code: StatusCode::BAD_REQUEST,
+            })?;
+        check_batch_len(metas.len(), MAX_BATCH_ITEMS)?;
+        Some(metas)
+    };
+
+    let client = get_client_with_cd_cookie(true, cd_auth_data.cookie)?;
+
+    // without an explicit list, query every subgrade that has stats on CampusDual, up to the
+    // same limit; X-Batch-Total tells the client that there are more to request explicitly
+    let mut headers = HeaderMap::new();
+    let subgrade_metas = match subgrade_metas {
+        Some(metas) => metas,
+        None => {
+            let mut metas = fetch_grades(&client)
+                .await?
+                .grades
+                .into_iter()
+                .flat_map(|grade| grade.subgrades)
+                .filter_map(|subgrade| subgrade.internal_metadata)
+                .collect::<Vec<_>>();
+            if metas.len() > MAX_BATCH_ITEMS {
+                headers.insert(BATCH_TOTAL_HEADER, HeaderValue::from(metas.len()));
+                metas.truncate(MAX_BATCH_ITEMS);
+            }
+            metas
+        }
+    };
+
+    let concurrency = (*BATCH_CONCURRENCY.get().unwrap()).max(1);
+    let all_stats = stream::iter(subgrade_metas)
+        .map(|subgrade_meta| {
+            let client = &client;
+            async move {
+                let key = format!(
+                    "{}/{}/{}",
+                    subgrade_meta.module, subgrade_meta.peryr, subgrade_meta.perid
+                );
+                let entry = match fetch_gradestats(client, &subgrade_meta).await {
+                    Ok(stats) => GradeStatsBatchEntry::Stats(stats),
+                    Err(e) => GradeStatsBatchEntry::Error(e.message),
+                };
+                (key, entry)
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect::<HashMap<_, _>>()
+        .await;
+
+    Ok((headers, Json(all_stats)))
+}
+
+// every item is a CampusDual call, the per-JWT rate limit only sees the one request
+pub fn check_batch_len(len: usize, max: usize) -> Result<(), ResponseError> {
+    if len > max {
+        return Err(ResponseError {
+            message: format!("Maximal {max} Einträge pro Anfrage"),
+            status_code: StatusCode::BAD_REQUEST,
+        });
+    }
+    Ok(())
+}
+
+async fn fetch_gradestats(
+    client: &ClientWithMiddleware,
+    subgrade_meta: &SubGradeMetadata,
+) -> Result<GradeStatsAllStudents, ResponseError> {
     let grade_stats: Vec<CdGradeStatEntry> = client
         .get(format!(
             "https://selfservice.campus-dual.de/acwork/mscoredist?module={}&peryr={}&perid={}",
@@ -81,7 +295,7 @@ pub async fn get_gradestats(
         }
     }
 
-    Ok(Json(all_stats))
+    Ok(all_stats)
 }
 
 pub async fn check_revive_session(
@@ -126,41 +340,30 @@ pub async fn check_revive_session(
 
 pub async fn get_examsignup(
     Extension(cd_auth_data): Extension<CdAuthData>,
-) -> Result<Json<Vec<CampusDualSignupOption>>, ResponseError> {
+) -> Result<(HeaderMap, Json<Vec<CampusDualSignupOption>>), ResponseError> {
     let client = get_client_with_cd_cookie(true, cd_auth_data.cookie)?;
-    let exam_signup_html = client
-        .get("https://selfservice.campus-dual.de/acwork/expproc")
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
-
-    let signup_options = extract_exam_signup_options(exam_signup_html).await?;
+    let extraction = fetch_exam_signup_options(&client).await?;
 
-    Ok(Json(signup_options))
+    Ok((
+        parser_warnings_header(&extraction.warnings)?,
+        Json(extraction.options),
+    ))
 }
 
 pub async fn post_registerexam(
     Extension(cd_auth_data): Extension<CdAuthData>,
     Json(examregist_meta): Json<ExamRegistrationMetadata>,
-) -> Result<String, ResponseError> {
-    let client = get_client_default(true)?;
-    let exam_regist_resp = client
-        .get(format!(
-            "https://selfservice.campus-dual.de/acwork/registerexam?userid={}&assessment={}&peryr={}&perid={}&offerno={}&hash={}",
-            cd_auth_data.user,
-            examregist_meta.assessment,
-            examregist_meta.peryr,
-            examregist_meta.perid,
-            examregist_meta.offerno,
-            cd_auth_data.hash,
-        ))
-        .send()
-        .await?
-        .error_for_status()?;
+) -> Result<ExamActionResult, ExamActionError> {
+    register_exam(&cd_auth_data, &examregist_meta).await
+}
 
-    Ok(exam_regist_resp.text().await?)
+pub async fn post_registerexam_bulk(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(bulk_request): Json<BulkExamActionRequest>,
+) -> Result<Json<Vec<BulkExamActionItem>>, ResponseError> {
+    Ok(Json(
+        bulk_exam_action(&cd_auth_data, bulk_request, ExamActionKind::Register).await?,
+    ))
 }
 
 pub async fn get_examdetails(
@@ -168,72 +371,68 @@ pub async fn get_examdetails(
     Json(examregist_meta): Json<ExamRegistrationMetadata>,
 ) -> Result<Json<CdExamDetails>, ResponseError> {
     let client = get_client_default(true)?;
-    let mut exam_details: CdExamDetails = client
-        .get(format!(
-            "https://selfservice.campus-dual.de/acwork/offerdetail?user={}&objidexm=undefined&evob_objid={}&peryr={}&perid={}&offerno={}",
-            cd_auth_data.user,
-            examregist_meta.assessment,
-            examregist_meta.peryr,
-            examregist_meta.perid,
-            examregist_meta.offerno,
-        ))
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?;
 
-    let examorg_long = {
-        let resp = client
-            .get(format!(
-                "https://selfservice.campus-dual.de/acwork/examorg?examorg={}",
-                exam_details.ev_examorg_text
-            ))
-            .send()
-            .await?
-            .error_for_status();
-        match resp {
-            Ok(resp) => serde_json::from_str(&resp.text().await?)?,
-            Err(_) => exam_details.ev_examorg_text.clone(),
-        }
-    };
+    Ok(Json(
+        fetch_exam_details_with_longtext(&client, &cd_auth_data, &examregist_meta).await?,
+    ))
+}
+
+pub async fn get_examdetails_batch(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(examregist_metas): Json<Vec<ExamRegistrationMetadata>>,
+) -> Result<Json<HashMap<String, ExamDetailsBatchEntry>>, ResponseError> {
+    check_batch_len(examregist_metas.len(), MAX_BATCH_ITEMS)?;
+    let client = get_client_default(true)?;
+
+    let concurrency = (*BATCH_CONCURRENCY.get().unwrap()).max(1);
+    let all_details = stream::iter(examregist_metas)
+        .map(|examregist_meta| {
+            let client = &client;
+            let cd_auth_data = &cd_auth_data;
+            async move {
+                let entry =
+                    match fetch_exam_details_with_longtext(client, cd_auth_data, &examregist_meta)
+                        .await
+                    {
+                        Ok(details) => ExamDetailsBatchEntry::Details(Box::new(details)),
+                        Err(e) => ExamDetailsBatchEntry::Error(e.message),
+                    };
+                (offer_key(&examregist_meta), entry)
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect::<HashMap<_, _>>()
+        .await;
 
-    exam_details.ev_examorg_longtext = Some(examorg_long);
-    Ok(Json(exam_details))
+    Ok(Json(all_details))
 }
 
 pub async fn post_cancelexam(
     Extension(cd_auth_data): Extension<CdAuthData>,
     Json(examregist_meta): Json<ExamRegistrationMetadata>,
-) -> Result<String, ResponseError> {
-    let client = get_client_default(true)?;
-    let exam_regist_resp = client
-        .get(format!(
-            "https://selfservice.campus-dual.de/acwork/cancelexam?userid={}&objid={}&hash={}",
-            cd_auth_data.user, examregist_meta.assessment, cd_auth_data.hash
-        ))
-        .send()
-        .await?
-        .error_for_status()?;
+) -> Result<ExamActionResult, ExamActionError> {
+    cancel_exam(&cd_auth_data, &examregist_meta).await
+}
 
-    Ok(exam_regist_resp.text().await?)
+pub async fn post_cancelexam_bulk(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(bulk_request): Json<BulkExamActionRequest>,
+) -> Result<Json<Vec<BulkExamActionItem>>, ResponseError> {
+    Ok(Json(
+        bulk_exam_action(&cd_auth_data, bulk_request, ExamActionKind::Cancel).await?,
+    ))
 }
 
 pub async fn get_examverfahren(
     Extension(cd_auth_data): Extension<CdAuthData>,
-) -> Result<Json<Vec<CampusDualVerfahrenOption>>, ResponseError> {
+) -> Result<(HeaderMap, Json<Vec<CampusDualVerfahrenOption>>), ResponseError> {
     let client = get_client_with_cd_cookie(true, cd_auth_data.cookie)?;
-    let exam_verfahren_html = client
-        .get("https://selfservice.campus-dual.de/acwork/cancelproc")
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
-
-    let signup_verfahren = extract_exam_verfahren_options(exam_verfahren_html).await?;
+    let extraction = fetch_exam_verfahren_options(&client).await?;
 
-    Ok(Json(signup_verfahren))
+    Ok((
+        parser_warnings_header(&extraction.warnings)?,
+        Json(extraction.options),
+    ))
 }
 
 pub async fn get_ects(
@@ -316,95 +515,62 @@ pub async fn get_examstats(
 
 pub async fn get_stundenplan(
     Extension(cd_authdata): Extension<CdAuthData>,
+    Query(stundenplan_query): Query<StundenplanQuery>,
 ) -> Result<Json<Vec<StundenplanItem>>, ResponseError> {
     let client = get_client_default(true)?;
 
-    let user = cd_authdata.user;
-    let hash = cd_authdata.hash;
-
-    let mut stundenplan: Vec<StundenplanItem> = client
-        .get(format!(
-            "https://selfservice.campus-dual.de/room/json?userid={user}&hash={hash}&start=1720735200&end=1720821600"
-        ))
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?;
+    let (start, end) = stundenplan_range(&stundenplan_query)?;
+    let color_scheme = ColorScheme::from_query(&stundenplan_query)?;
+    let mut stundenplan =
+        fetch_group_stundenplan(&client, &cd_authdata, start.timestamp(), end.timestamp()).await?;
+    let colors = timetable_colors(&stundenplan, &color_scheme);
 
     for item in &mut stundenplan {
         item.start *= 1000;
         item.end *= 1000;
-        item.color = match item.color.as_str() {
-            "darkred" => "#D41610".to_string(),
-            _ => string_to_rgb(&format!("0{}0", item.title)),
-        };
-        item.font_color = Some(
-            if hex_to_luminance(&item.color) < 128.0 {
-                "#FFFFFF"
-            } else {
-                "#000000"
-            }
-            .to_string(),
+        let (color, font_color) = colors.event_colors(
+            &item.color,
+            &module_key(&item.title, &item.description),
+            &item.title,
         );
+        item.color = color;
+        item.font_color = Some(font_color);
     }
 
     Ok(Json(stundenplan))
 }
 
-fn string_to_rgb(input: &str) -> String {
-    // Create a hasher
-    let mut hasher = FnvHasher::default();
-
-    // Hash the input string
-    input.hash(&mut hasher);
-    let hash = hasher.finish();
-
-    // Extract RGB components from the hash
-    let r = (hash & 0xFF) as u8;
-    let g = ((hash >> 8) & 0xFF) as u8;
-    let b = ((hash >> 16) & 0xFF) as u8;
-
-    format!("#{:02X}{:02X}{:02X}", r, g, b)
-}
-
 pub async fn get_reminders(
     Extension(cd_authdata): Extension<CdAuthData>,
 ) -> Result<Json<CampusReminders>, ResponseError> {
     let client = get_client_default(true)?;
 
-    let user = cd_authdata.user;
-    let hash = cd_authdata.hash;
+    Ok(Json(fetch_reminders(&client, &cd_authdata).await?))
+}
 
-    let resp = client
+pub async fn fetch_reminders(
+    client: &ClientWithMiddleware,
+    cd_authdata: &CdAuthData,
+) -> Result<CampusReminders, ResponseError> {
+    Ok(client
         .get(format!(
-            "https://selfservice.campus-dual.de/dash/getreminders?user={user}&hash={hash}"
+            "https://selfservice.campus-dual.de/dash/getreminders?user={}&hash={}",
+            cd_authdata.user, cd_authdata.hash
         ))
         .send()
         .await?
         .error_for_status()?
         .json::<CampusReminders>()
-        .await?;
-
-    Ok(Json(resp))
+        .await?)
 }
 
 pub async fn get_timeline(
     Extension(cd_authdata): Extension<CdAuthData>,
 ) -> Result<Json<ExportTimelineEvents>, ResponseError> {
     let client = get_client_default(true)?;
-    let resp = client
-        .get(format!(
-            "https://selfservice.campus-dual.de/dash/gettimeline?user={}",
-            cd_authdata.user
-        ))
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<CampusTimeline>()
-        .await;
+    let resp = fetch_timeline(&client, &cd_authdata).await?;
 
-    if let Ok(timeline) = resp {
+    if let Some(timeline) = resp {
         let events = timeline.events;
 
         let fachsemester: Vec<ExportTimelineEvent> = events_by_color("#fcbe04", &events);
@@ -425,6 +591,24 @@ pub async fn get_timeline(
     }
 }
 
+// None if CampusDual sent something that isn't a timeline
+pub async fn fetch_timeline(
+    client: &ClientWithMiddleware,
+    cd_authdata: &CdAuthData,
+) -> Result<Option<CampusTimeline>, ResponseError> {
+    Ok(client
+        .get(format!(
+            "https://selfservice.campus-dual.de/dash/gettimeline?user={}",
+            cd_authdata.user
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<CampusTimeline>()
+        .await
+        .ok())
+}
+
 fn events_by_color(color: &str, events: &[CampusTimelineEvent]) -> Vec<ExportTimelineEvent> {
     events
         .iter()
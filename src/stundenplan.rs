@@ -0,0 +1,266 @@
+use axum::{extract::Query, Extension, Json};
+use chrono::{DateTime, Duration, TimeZone};
+use chrono_tz::{Europe::Berlin, Tz};
+use http::StatusCode;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    campus_backend::req_client_funcs::get_client_default,
+    color_stuff::{ColorScheme, TimetableColors},
+    group_timetable_cache::fetch_group_stundenplan,
+    room_directory::room_location,
+    time_stuff::{berlin_midnight, current_week_berlin},
+    timetable_exemptions::user_exemptions,
+    timetable_filter::TimetableFilter,
+    types::{
+        CdAuthData, ResponseError, StundenplanItem, StundenplanQuery, TimetableEvent,
+        TimetableEventStatus, TimetableRoom,
+    },
+};
+
+// keeps a single request from pulling years of events from CampusDual
+const MAX_RANGE_DAYS: i64 = 366;
+
+// start/end are unix timestamps in seconds, the items keep CampusDual's (seconds) timestamps
+pub async fn fetch_stundenplan(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    start: i64,
+    end: i64,
+) -> Result<Vec<StundenplanItem>, ResponseError> {
+    Ok(client
+        .get(format!(
+            "https://selfservice.campus-dual.de/room/json?userid={}&hash={}&start={start}&end={end}",
+            cd_auth_data.user, cd_auth_data.hash
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+// start of the first day until the end of the last day, in German local time
+pub fn stundenplan_range(
+    query: &StundenplanQuery,
+) -> Result<(DateTime<Tz>, DateTime<Tz>), ResponseError> {
+    let (week_start, week_end) = current_week_berlin();
+    let first_day = query.start.unwrap_or(week_start);
+    let last_day = query.end.unwrap_or(match query.start {
+        Some(start) => start + Duration::days(6),
+        None => week_end,
+    });
+
+    if last_day < first_day || (last_day - first_day).num_days() >= MAX_RANGE_DAYS {
+        return Err(ResponseError {
+            message: format!(
+                "Ungültiger Zeitraum (Ende vor Beginn oder mehr als {MAX_RANGE_DAYS} Tage)"
+            ),
+            status_code: StatusCode::BAD_REQUEST,
+        });
+    }
+
+    match (
+        berlin_midnight(first_day),
+        berlin_midnight(last_day + Duration::days(1)),
+    ) {
+        (Some(start), Some(end)) => Ok((start, end)),
+        _ => Err(ResponseError {
+            message: "Ungültiger Zeitraum".to_string(),
+            status_code: StatusCode::BAD_REQUEST,
+        }),
+    }
+}
+
+pub async fn fetch_timetable(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    start: &DateTime<Tz>,
+    end: &DateTime<Tz>,
+    color_scheme: &ColorScheme,
+) -> Result<Vec<TimetableEvent>, ResponseError> {
+    let items =
+        fetch_group_stundenplan(client, cd_auth_data, start.timestamp(), end.timestamp()).await?;
+    let colors = timetable_colors(&items, color_scheme);
+
+    let mut events = items
+        .iter()
+        .filter_map(|item| normalize_stundenplan_item(item, &colors))
+        .collect::<Vec<_>>();
+    events.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.title.cmp(&b.title)));
+
+    Ok(events)
+}
+
+pub async fn get_timetable(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Query(stundenplan_query): Query<StundenplanQuery>,
+) -> Result<Json<Vec<TimetableEvent>>, ResponseError> {
+    let client = get_client_default(true)?;
+    let (start, end) = stundenplan_range(&stundenplan_query)?;
+    let color_scheme = ColorScheme::from_query(&stundenplan_query)?;
+    let filter = TimetableFilter::from_query(
+        &stundenplan_query,
+        &user_exemptions(&cd_auth_data.user).await,
+    )?;
+
+    let events = fetch_timetable(&client, &cd_auth_data, &start, &end, &color_scheme).await?;
+    Ok(Json(filter.apply(events)))
+}
+
+// palette colors are handed out per module, so all items have to be known up front
+pub fn timetable_colors(items: &[StundenplanItem], color_scheme: &ColorScheme) -> TimetableColors {
+    let module_keys = items
+        .iter()
+        .map(|item| module_key(&item.title, &item.description))
+        .collect::<Vec<_>>();
+    color_scheme.assign(module_keys.iter().map(String::as_str))
+}
+
+// the module code if there is one, otherwise the title
+pub fn module_key(title: &str, description: &str) -> String {
+    split_module(title, description)
+        .0
+        .unwrap_or_else(|| title.trim().to_string())
+}
+
+// None only for timestamps chrono can't represent
+pub fn normalize_stundenplan_item(
+    item: &StundenplanItem,
+    colors: &TimetableColors,
+) -> Option<TimetableEvent> {
+    let start = Berlin.timestamp_opt(item.start, 0).single()?;
+    let end = Berlin.timestamp_opt(item.end, 0).single()?;
+
+    let (module_code, module_title) = split_module(&item.title, &item.description);
+    let (color, font_color) = colors.event_colors(
+        &item.color,
+        module_code.as_deref().unwrap_or(item.title.trim()),
+        &item.title,
+    );
+
+    let remarks = Some(item.remarks.trim())
+        .filter(|remarks| !remarks.is_empty())
+        .map(|remarks| remarks.to_string());
+
+    Some(TimetableEvent {
+        start,
+        end,
+        all_day: item.all_day,
+        title: item.title.trim().to_string(),
+        module_code,
+        module_title,
+        description: item.description.trim().to_string(),
+        room: split_room(&item.sroom, &item.room),
+        instructors: split_names(&item.instructor),
+        instructors_short: split_names(&item.sinstructor),
+        status: event_status(remarks.as_deref(), &item.color),
+        highlighted: item.color == "darkred",
+        remarks,
+        color,
+        font_color,
+    })
+}
+
+// codes look like "5CS-MA1-10" or "WI21-BWL.2", either in the title or in the description
+fn split_module(title: &str, description: &str) -> (Option<String>, String) {
+    lazy_static! {
+        static ref MODULE_CODE_RE: Regex =
+            Regex::new(r"\b(\d?[A-Z]{2,}[A-Z0-9]*(?:[-_.][A-Z0-9]+)+)\b").unwrap();
+    }
+
+    let title = title.trim();
+    let code = MODULE_CODE_RE
+        .find(title)
+        .or_else(|| MODULE_CODE_RE.find(description))
+        .map(|code| code.as_str().to_string());
+
+    let module_title = match &code {
+        Some(code) => title
+            .replace(code.as_str(), "")
+            .trim_matches(|c: char| c.is_whitespace() || "-:()[]".contains(c))
+            .to_string(),
+        None => title.to_string(),
+    };
+
+    // a title that only consisted of the code
+    let module_title = if module_title.is_empty() {
+        description.trim().to_string()
+    } else {
+        module_title
+    };
+
+    (code, module_title)
+}
+
+// sroom is the short name ("5.101"), room the long one ("Hörsaal 5.101")
+fn split_room(short: &str, long: &str) -> Option<TimetableRoom> {
+    let (short, long) = (short.trim(), long.trim());
+    match (short.is_empty(), long.is_empty()) {
+        (true, true) => None,
+        (true, false) => Some(TimetableRoom {
+            short: long.to_string(),
+            long: long.to_string(),
+            location: room_location(long),
+        }),
+        (false, true) => Some(TimetableRoom {
+            short: short.to_string(),
+            long: short.to_string(),
+            location: room_location(short),
+        }),
+        (false, false) => Some(TimetableRoom {
+            short: short.to_string(),
+            long: long.to_string(),
+            location: room_location(short).or_else(|| room_location(long)),
+        }),
+    }
+}
+
+// several instructors are separated by ";", "/" or " und ", commas are part of "Name, Vorname"
+pub fn split_names(names: &str) -> Vec<String> {
+    names
+        .split([';', '/'])
+        .flat_map(|name| name.split(" und "))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// CampusDual has no status field, cancellations and changes only show up in the remarks
+// (and as a greyed out color)
+fn event_status(remarks: Option<&str>, cd_color: &str) -> TimetableEventStatus {
+    const CANCELLED: [&str; 6] = [
+        "fällt aus",
+        "entfällt",
+        "ausfall",
+        "abgesagt",
+        "storniert",
+        "cancelled",
+    ];
+    const CHANGED: [&str; 7] = [
+        "verlegt",
+        "verschoben",
+        "geändert",
+        "änderung",
+        "neuer raum",
+        "ersatztermin",
+        "changed",
+    ];
+
+    let remarks = remarks.unwrap_or_default().to_lowercase();
+    if CANCELLED.iter().any(|keyword| remarks.contains(keyword))
+        || matches!(
+            cd_color.to_lowercase().as_str(),
+            "gray" | "grey" | "lightgray" | "lightgrey" | "silver"
+        )
+    {
+        TimetableEventStatus::Cancelled
+    } else if CHANGED.iter().any(|keyword| remarks.contains(keyword)) {
+        TimetableEventStatus::Changed
+    } else {
+        TimetableEventStatus::Regular
+    }
+}
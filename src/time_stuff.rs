@@ -0,0 +1,49 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::{Europe::Berlin, Tz};
+
+// CampusDual and all of its dates and deadlines live in German local time
+pub fn berlin_now() -> DateTime<Tz> {
+    Utc::now().with_timezone(&Berlin)
+}
+
+// SAP date fields come as "20240612", "2024-06-12" or "12.06.2024" depending on the endpoint,
+// "00000000" means no date at all
+pub fn parse_sap_date(raw: &str) -> Option<NaiveDate> {
+    let raw = raw.trim();
+    ["%Y%m%d", "%Y-%m-%d", "%d.%m.%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(raw, format).ok())
+}
+
+pub fn parse_sap_time(raw: &str) -> Option<NaiveTime> {
+    let raw = raw.trim();
+    ["%H%M%S", "%H:%M:%S", "%H:%M"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(raw, format).ok())
+}
+
+// a missing time means start of day
+pub fn parse_sap_datetime(date: &str, time: &str) -> Option<NaiveDateTime> {
+    Some(parse_sap_date(date)?.and_time(parse_sap_time(time).unwrap_or(NaiveTime::MIN)))
+}
+
+pub fn berlin_to_utc(local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    Berlin
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|date_time| date_time.with_timezone(&Utc))
+}
+
+// Monday and Sunday of the current week
+pub fn current_week_berlin() -> (NaiveDate, NaiveDate) {
+    let today = berlin_now().date_naive();
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    (monday, monday + Duration::days(6))
+}
+
+// local midnight, the earlier one if DST makes it ambiguous
+pub fn berlin_midnight(date: NaiveDate) -> Option<DateTime<Tz>> {
+    Berlin
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::Query, Extension, Json};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Weekday};
+use chrono_tz::{Europe::Berlin, Tz};
+use http::StatusCode;
+
+use crate::{
+    campus_backend::req_client_funcs::get_client_default,
+    color_stuff::ColorScheme,
+    stundenplan::{fetch_timetable, stundenplan_range},
+    time_stuff::parse_sap_time,
+    types::{
+        CdAuthData, ResponseError, StundenplanQuery, TimetableAnalysis, TimetableAnalysisQuery,
+        TimetableDayAnalysis, TimetableEvent, TimetableEventStatus, TimetableFreeSlot,
+        TimetableModuleWorkload, TimetableWeekWorkload,
+    },
+};
+
+const DEFAULT_DAY_START: NaiveTime = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+const DEFAULT_DAY_END: NaiveTime = NaiveTime::from_hms_opt(18, 0, 0).unwrap();
+const DEFAULT_MIN_SLOT_MINUTES: i64 = 30;
+
+pub async fn get_timetable_analysis(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Query(analysis_query): Query<TimetableAnalysisQuery>,
+) -> Result<Json<TimetableAnalysis>, ResponseError> {
+    let bad_request = |message: &str| ResponseError {
+        message: message.to_string(),
+        status_code: StatusCode::BAD_REQUEST,
+    };
+    let parse_bound = |raw: &Option<String>, default: NaiveTime| match raw {
+        Some(raw) => parse_sap_time(raw).ok_or(bad_request("Ungültige Uhrzeit (HH:MM)")),
+        None => Ok(default),
+    };
+    let day_start = parse_bound(&analysis_query.day_start, DEFAULT_DAY_START)?;
+    let day_end = parse_bound(&analysis_query.day_end, DEFAULT_DAY_END)?;
+    if day_end <= day_start {
+        return Err(bad_request("day_end muss nach day_start liegen"));
+    }
+    let min_slot_minutes = analysis_query
+        .min_slot_minutes
+        .unwrap_or(DEFAULT_MIN_SLOT_MINUTES)
+        .max(1);
+
+    let (start, end) = stundenplan_range(&StundenplanQuery {
+        start: analysis_query.start,
+        end: analysis_query.end,
+        ..Default::default()
+    })?;
+
+    let client = get_client_default(true)?;
+    let events = fetch_timetable(
+        &client,
+        &cd_auth_data,
+        &start,
+        &end,
+        &ColorScheme::default(),
+    )
+    .await?;
+
+    // the range end is the midnight after the last day
+    let first_day = start.date_naive();
+    let last_day = (end - Duration::seconds(1)).date_naive();
+
+    Ok(Json(analyze_timetable(
+        &events,
+        first_day,
+        last_day,
+        day_start,
+        day_end,
+        min_slot_minutes,
+        analysis_query.include_weekends,
+    )))
+}
+
+// cancelled lectures don't take up time, all day events (holidays, practical phases) are only listed
+pub fn analyze_timetable(
+    events: &[TimetableEvent],
+    first_day: NaiveDate,
+    last_day: NaiveDate,
+    day_start: NaiveTime,
+    day_end: NaiveTime,
+    min_slot_minutes: i64,
+    include_weekends: bool,
+) -> TimetableAnalysis {
+    let lectures = events
+        .iter()
+        .filter(|event| event.status != TimetableEventStatus::Cancelled && event.end > event.start)
+        .collect::<Vec<_>>();
+
+    let mut days = Vec::new();
+    let mut free_days = Vec::new();
+    let mut weeks: BTreeMap<(i32, u32), TimetableWeekWorkload> = BTreeMap::new();
+
+    for date in first_day.iter_days().take_while(|date| *date <= last_day) {
+        let day_lectures = lectures
+            .iter()
+            .filter(|event| !event.all_day && event.start.date_naive() == date)
+            .collect::<Vec<_>>();
+        let all_day_events = lectures
+            .iter()
+            .filter(|event| {
+                event.all_day && event.start.date_naive() <= date && event.end.date_naive() >= date
+            })
+            .map(|event| event.title.clone())
+            .collect::<Vec<_>>();
+
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        if weekend && !include_weekends && day_lectures.is_empty() {
+            continue;
+        }
+
+        let busy = merge_intervals(
+            day_lectures
+                .iter()
+                .map(|event| (event.start, event.end))
+                .collect(),
+        );
+        let contact_minutes = busy
+            .iter()
+            .map(|(start, end)| (*end - *start).num_minutes())
+            .sum::<i64>();
+
+        let iso_week = date.iso_week();
+        weeks
+            .entry((iso_week.year(), iso_week.week()))
+            .or_insert_with(|| TimetableWeekWorkload {
+                iso_year: iso_week.year(),
+                iso_week: iso_week.week(),
+                monday: date - Duration::days(date.weekday().num_days_from_monday() as i64),
+                contact_hours: 0.0,
+            })
+            .contact_hours += contact_minutes as f64 / 60.0;
+
+        if day_lectures.is_empty() {
+            free_days.push(date);
+        }
+
+        days.push(TimetableDayAnalysis {
+            date,
+            first_lecture: busy.first().map(|(start, _)| *start),
+            last_lecture_end: busy.last().map(|(_, end)| *end),
+            contact_minutes,
+            free_slots: free_slots(date, &busy, day_start, day_end, min_slot_minutes),
+            all_day_events,
+        });
+    }
+
+    let mut modules: BTreeMap<String, TimetableModuleWorkload> = BTreeMap::new();
+    for event in lectures.iter().filter(|event| {
+        !event.all_day
+            && event.start.date_naive() >= first_day
+            && event.start.date_naive() <= last_day
+    }) {
+        let module = modules
+            .entry(event.module_code.clone().unwrap_or(event.title.clone()))
+            .or_insert_with(|| TimetableModuleWorkload {
+                module_code: event.module_code.clone(),
+                module_title: event.module_title.clone(),
+                lectures: 0,
+                contact_hours: 0.0,
+            });
+        module.lectures += 1;
+        module.contact_hours += (event.end - event.start).num_minutes() as f64 / 60.0;
+    }
+    let mut modules = modules.into_values().collect::<Vec<_>>();
+    modules.sort_by(|a, b| b.contact_hours.total_cmp(&a.contact_hours));
+
+    let total_contact_minutes = days.iter().map(|day| day.contact_minutes).sum::<i64>();
+
+    TimetableAnalysis {
+        start: first_day,
+        end: last_day,
+        day_start,
+        day_end,
+        total_contact_hours: total_contact_minutes as f64 / 60.0,
+        days,
+        free_days,
+        weeks: weeks.into_values().collect(),
+        modules,
+    }
+}
+
+// sorted, overlapping and touching intervals joined
+fn merge_intervals(
+    mut intervals: Vec<(DateTime<Tz>, DateTime<Tz>)>,
+) -> Vec<(DateTime<Tz>, DateTime<Tz>)> {
+    intervals.sort_by_key(|(start, _)| *start);
+
+    let mut merged: Vec<(DateTime<Tz>, DateTime<Tz>)> = Vec::new();
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// gaps between the lectures, limited to the day bounds
+fn free_slots(
+    date: NaiveDate,
+    busy: &[(DateTime<Tz>, DateTime<Tz>)],
+    day_start: NaiveTime,
+    day_end: NaiveTime,
+    min_slot_minutes: i64,
+) -> Vec<TimetableFreeSlot> {
+    let local = |time: NaiveTime| Berlin.from_local_datetime(&date.and_time(time)).earliest();
+    let (Some(day_start), Some(day_end)) = (local(day_start), local(day_end)) else {
+        return Vec::new();
+    };
+
+    let mut slots = Vec::new();
+    let mut cursor = day_start;
+    for (start, end) in busy
+        .iter()
+        .chain([(day_end, day_end)].iter())
+        .filter(|(_, end)| *end > day_start)
+    {
+        let slot_end = (*start).min(day_end);
+        if slot_end > cursor && (slot_end - cursor).num_minutes() >= min_slot_minutes {
+            slots.push(TimetableFreeSlot {
+                start: cursor,
+                end: slot_end,
+                minutes: (slot_end - cursor).num_minutes(),
+            });
+        }
+        cursor = cursor.max(*end);
+        if cursor >= day_end {
+            break;
+        }
+    }
+    slots
+}
@@ -0,0 +1,419 @@
+use std::hash::{Hash, Hasher};
+
+use axum::{Extension, Json};
+use chrono::{DateTime, Duration, FixedOffset};
+use fnv::FnvHasher;
+use http::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+
+use crate::{
+    campus_backend::req_client_funcs::get_client_default,
+    color_stuff::ColorScheme,
+    group_timetable_cache::invalidate_group,
+    stundenplan::fetch_timetable,
+    time_stuff::berlin_now,
+    types::{
+        CdAuthData, ResponseError, TimetableChange, TimetableChangesRequest,
+        TimetableChangesResponse, TimetableEvent, TimetableSnapshotEntry,
+    },
+};
+
+pub const DEFAULT_CHANGE_DAYS: i64 = 14;
+pub const MAX_CHANGE_DAYS: i64 = 60;
+// a lecture that shows up again further away than this is reported as removed + added
+const MAX_MOVE_DAYS: i64 = 14;
+
+pub async fn post_timetable_changes(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(changes_request): Json<TimetableChangesRequest>,
+) -> Result<Json<TimetableChangesResponse>, ResponseError> {
+    let days = change_days(changes_request.days)?;
+    let client = get_client_default(true)?;
+
+    let (snapshot, horizon) = fetch_snapshot(&client, &cd_auth_data, days).await?;
+    let fingerprint = snapshot_fingerprint(&snapshot);
+
+    let changes = match &changes_request.snapshot {
+        Some(previous) => {
+            let (previous, current) =
+                comparable_snapshots(previous, changes_request.horizon, &snapshot, horizon);
+            diff_snapshots(&previous, &current)
+        }
+        None => Vec::new(),
+    };
+    // the shared timetable of the seminar group is likely outdated as well
+    if !changes.is_empty() {
+        invalidate_group(&cd_auth_data.seminar_group);
+    }
+    let unchanged = match (&changes_request.snapshot, &changes_request.fingerprint) {
+        (Some(_), _) => changes.is_empty(),
+        (None, Some(previous_fingerprint)) => *previous_fingerprint == fingerprint,
+        (None, None) => false,
+    };
+
+    Ok(Json(TimetableChangesResponse {
+        fingerprint,
+        unchanged,
+        changes,
+        snapshot,
+        horizon,
+    }))
+}
+
+pub fn change_days(days: Option<i64>) -> Result<i64, ResponseError> {
+    match days.unwrap_or(DEFAULT_CHANGE_DAYS) {
+        days @ 1..=MAX_CHANGE_DAYS => Ok(days),
+        _ => Err(ResponseError {
+            message: format!("days muss zwischen 1 und {MAX_CHANGE_DAYS} liegen"),
+            status_code: StatusCode::BAD_REQUEST,
+        }),
+    }
+}
+
+// from now until `days` days ahead, along with that horizon
+pub async fn fetch_snapshot(
+    client: &ClientWithMiddleware,
+    cd_auth_data: &CdAuthData,
+    days: i64,
+) -> Result<(Vec<TimetableSnapshotEntry>, DateTime<FixedOffset>), ResponseError> {
+    let now = berlin_now();
+    let horizon = now + Duration::days(days);
+    let events = fetch_timetable(
+        client,
+        cd_auth_data,
+        &now,
+        &horizon,
+        &ColorScheme::default(),
+    )
+    .await?;
+
+    let horizon = horizon.fixed_offset();
+    Ok((window(&snapshot_from_events(&events), horizon), horizon))
+}
+
+pub fn snapshot_from_events(events: &[TimetableEvent]) -> Vec<TimetableSnapshotEntry> {
+    let mut snapshot = events
+        .iter()
+        .map(|event| TimetableSnapshotEntry {
+            title: event.title.clone(),
+            start: event.start.fixed_offset(),
+            end: event.end.fixed_offset(),
+            room: event.room.as_ref().map(|room| room.short.clone()),
+            status: event.status,
+            highlighted: event.highlighted,
+        })
+        .collect::<Vec<_>>();
+    snapshot.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.title.cmp(&b.title)));
+    snapshot
+}
+
+// lectures that are over (or too far ahead) are neither added nor removed
+fn window(
+    snapshot: &[TimetableSnapshotEntry],
+    horizon: DateTime<FixedOffset>,
+) -> Vec<TimetableSnapshotEntry> {
+    let now = berlin_now().fixed_offset();
+    snapshot
+        .iter()
+        .filter(|entry| entry.end >= now && entry.start <= horizon)
+        .cloned()
+        .collect()
+}
+
+// Only the time both snapshots cover can be compared: lectures that ended since the previous
+// snapshot or just entered the horizon are no changes. Without a known previous horizon, its last
+// lecture is the best guess.
+pub fn comparable_snapshots(
+    previous: &[TimetableSnapshotEntry],
+    previous_horizon: Option<DateTime<FixedOffset>>,
+    current: &[TimetableSnapshotEntry],
+    horizon: DateTime<FixedOffset>,
+) -> (Vec<TimetableSnapshotEntry>, Vec<TimetableSnapshotEntry>) {
+    let previous_horizon = previous_horizon
+        .or_else(|| previous.iter().map(|entry| entry.start).max())
+        .unwrap_or(horizon);
+    let overlap = horizon.min(previous_horizon);
+    (window(previous, overlap), window(current, overlap))
+}
+
+pub fn snapshot_fingerprint(snapshot: &[TimetableSnapshotEntry]) -> String {
+    let mut hasher = FnvHasher::default();
+    for entry in snapshot {
+        entry.title.hash(&mut hasher);
+        entry.start.timestamp().hash(&mut hasher);
+        entry.end.timestamp().hash(&mut hasher);
+        entry.room.hash(&mut hasher);
+        (entry.status as u8).hash(&mut hasher);
+        entry.highlighted.hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+// Lectures have no id, so they are matched by title: same time first, then the closest time
+pub fn diff_snapshots(
+    previous: &[TimetableSnapshotEntry],
+    current: &[TimetableSnapshotEntry],
+) -> Vec<TimetableChange> {
+    let mut previous = previous.iter().map(Some).collect::<Vec<_>>();
+    let mut current = current.iter().map(Some).collect::<Vec<_>>();
+    let mut changes = Vec::new();
+
+    // 1. untouched lectures
+    for new_slot in current.iter_mut() {
+        let Some(new) = *new_slot else { continue };
+        if let Some(old_slot) = previous.iter_mut().find(|old| *old == &Some(new)) {
+            *old_slot = None;
+            *new_slot = None;
+        }
+    }
+
+    // 2. same lecture at the same time, but something else changed
+    for new_slot in current.iter_mut() {
+        let Some(new) = *new_slot else { continue };
+        let Some(old_slot) = previous.iter_mut().find(|old| {
+            old.is_some_and(|old| {
+                old.title == new.title && old.start == new.start && old.end == new.end
+            })
+        }) else {
+            continue;
+        };
+        let old = old_slot.take().unwrap();
+        *new_slot = None;
+
+        if old.room != new.room {
+            changes.push(TimetableChange::RoomChanged {
+                event: new.clone(),
+                previous_room: old.room.clone(),
+            });
+        }
+        if old.status != new.status {
+            changes.push(TimetableChange::StatusChanged {
+                event: new.clone(),
+                previous_status: old.status,
+            });
+        }
+        if new.highlighted && !old.highlighted {
+            changes.push(TimetableChange::Highlighted { event: new.clone() });
+        }
+    }
+
+    // 3. same lecture at a different time
+    for new_slot in current.iter_mut() {
+        let Some(new) = *new_slot else { continue };
+        let closest = previous
+            .iter_mut()
+            .filter(|old| {
+                old.is_some_and(|old| {
+                    old.title == new.title
+                        && (old.start - new.start).num_days().abs() <= MAX_MOVE_DAYS
+                })
+            })
+            .min_by_key(|old| (old.unwrap().start - new.start).num_seconds().abs());
+        let Some(old_slot) = closest else { continue };
+        let old = old_slot.take().unwrap();
+        *new_slot = None;
+
+        changes.push(TimetableChange::Moved {
+            event: new.clone(),
+            previous_start: old.start,
+            previous_end: old.end,
+            previous_room: (old.room != new.room).then(|| old.room.clone()).flatten(),
+        });
+    }
+
+    changes.extend(
+        previous
+            .into_iter()
+            .flatten()
+            .map(|old| TimetableChange::Removed { event: old.clone() }),
+    );
+    changes.extend(
+        current
+            .into_iter()
+            .flatten()
+            .map(|new| TimetableChange::Added { event: new.clone() }),
+    );
+
+    changes.sort_by_key(|change| change_event(change).start);
+    changes
+}
+
+pub fn change_event(change: &TimetableChange) -> &TimetableSnapshotEntry {
+    match change {
+        TimetableChange::Added { event }
+        | TimetableChange::Removed { event }
+        | TimetableChange::Moved { event, .. }
+        | TimetableChange::RoomChanged { event, .. }
+        | TimetableChange::StatusChanged { event, .. }
+        | TimetableChange::Highlighted { event } => event,
+    }
+}
+
+// one line per change for e-mails and chat webhooks
+pub fn describe_change(change: &TimetableChange) -> String {
+    let when = |event: &TimetableSnapshotEntry| {
+        format!(
+            "{} {}-{}",
+            event.start.format("%d.%m.%Y"),
+            event.start.format("%H:%M"),
+            event.end.format("%H:%M")
+        )
+    };
+    let room = |room: &Option<String>| room.clone().unwrap_or_else(|| "-".to_string());
+
+    match change {
+        TimetableChange::Added { event } => {
+            format!(
+                "Neu: {} am {} ({})",
+                event.title,
+                when(event),
+                room(&event.room)
+            )
+        }
+        TimetableChange::Removed { event } => {
+            format!("Entfernt: {} am {}", event.title, when(event))
+        }
+        TimetableChange::Moved {
+            event,
+            previous_start,
+            ..
+        } => format!(
+            "Verschoben: {} vom {} auf {} ({})",
+            event.title,
+            previous_start.format("%d.%m.%Y %H:%M"),
+            when(event),
+            room(&event.room)
+        ),
+        TimetableChange::RoomChanged {
+            event,
+            previous_room,
+        } => format!(
+            "Raumänderung: {} am {}: {} -> {}",
+            event.title,
+            when(event),
+            room(previous_room),
+            room(&event.room)
+        ),
+        TimetableChange::StatusChanged { event, .. } => format!(
+            "Statusänderung: {} am {}: {:?}",
+            event.title,
+            when(event),
+            event.status
+        ),
+        TimetableChange::Highlighted { event } => {
+            format!("Markiert: {} am {}", event.title, when(event))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TimetableEventStatus;
+
+    fn entry(title: &str, start: DateTime<FixedOffset>, room: &str) -> TimetableSnapshotEntry {
+        TimetableSnapshotEntry {
+            title: title.to_string(),
+            start,
+            end: start + Duration::minutes(90),
+            room: Some(room.to_string()),
+            status: TimetableEventStatus::Regular,
+            highlighted: false,
+        }
+    }
+
+    fn tomorrow() -> DateTime<FixedOffset> {
+        berlin_now().fixed_offset() + Duration::days(1)
+    }
+
+    #[test]
+    fn unchanged_snapshot_has_no_changes() {
+        let snapshot = vec![
+            entry("Mathe", tomorrow(), "A1"),
+            entry("BWL", tomorrow(), "A2"),
+        ];
+        assert!(diff_snapshots(&snapshot, &snapshot).is_empty());
+    }
+
+    #[test]
+    fn detects_room_change() {
+        let start = tomorrow();
+        let previous = vec![entry("Mathe", start, "A1")];
+        let current = vec![entry("Mathe", start, "B2")];
+        let changes = diff_snapshots(&previous, &current);
+        assert!(matches!(
+            changes.as_slice(),
+            [TimetableChange::RoomChanged { previous_room: Some(room), .. }] if room == "A1"
+        ));
+    }
+
+    #[test]
+    fn matches_moved_lecture_to_closest_time() {
+        let start = tomorrow();
+        let previous = vec![
+            entry("Mathe", start, "A1"),
+            entry("Mathe", start + Duration::days(7), "A1"),
+        ];
+        let current = vec![
+            entry("Mathe", start + Duration::hours(2), "A1"),
+            entry("Mathe", start + Duration::days(7), "A1"),
+        ];
+        let changes = diff_snapshots(&previous, &current);
+        assert!(matches!(
+            changes.as_slice(),
+            [TimetableChange::Moved { previous_start, previous_room: None, .. }]
+                if *previous_start == start
+        ));
+    }
+
+    #[test]
+    fn reports_added_and_removed_lectures() {
+        let previous = vec![entry("Mathe", tomorrow(), "A1")];
+        let current = vec![entry("BWL", tomorrow() + Duration::days(30), "A1")];
+        let changes = diff_snapshots(&previous, &current);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(changes[0], TimetableChange::Removed { .. }));
+        assert!(matches!(changes[1], TimetableChange::Added { .. }));
+    }
+
+    #[test]
+    fn compares_only_the_common_horizon() {
+        let start = tomorrow();
+        let previous = vec![entry("Mathe", start, "A1")];
+        // one more week fetched this time
+        let current = vec![
+            entry("Mathe", start, "A1"),
+            entry("BWL", start + Duration::days(10), "A1"),
+        ];
+        let (previous, current) = comparable_snapshots(
+            &previous,
+            Some(start + Duration::days(7)),
+            &current,
+            start + Duration::days(14),
+        );
+        assert!(diff_snapshots(&previous, &current).is_empty());
+    }
+
+    #[test]
+    fn guesses_previous_horizon_from_last_lecture() {
+        let start = tomorrow();
+        let previous = vec![entry("Mathe", start, "A1")];
+        let current = vec![
+            entry("Mathe", start, "A1"),
+            entry("BWL", start + Duration::days(1), "A1"),
+        ];
+        let (previous, current) =
+            comparable_snapshots(&previous, None, &current, start + Duration::days(14));
+        assert_eq!(previous.len(), 1);
+        assert_eq!(current.len(), 1);
+    }
+
+    #[test]
+    fn ignores_lectures_that_are_over() {
+        let past = berlin_now().fixed_offset() - Duration::days(1);
+        let previous = vec![entry("Mathe", past, "A1")];
+        let (previous, current) =
+            comparable_snapshots(&previous, None, &[], tomorrow() + Duration::days(7));
+        assert!(previous.is_empty() && current.is_empty());
+    }
+}
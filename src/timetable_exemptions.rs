@@ -0,0 +1,122 @@
+// Opt-in (TIMETABLE_EXEMPTIONS_FILE) list of modules a user is exempt from (recognised prior
+// learning). Only module codes/titles are stored, keyed by the CampusDual user.
+
+use std::{collections::HashMap, fs};
+
+use axum::{Extension, Json};
+use http::StatusCode;
+use lazy_static::lazy_static;
+use tokio::sync::Mutex;
+
+use crate::{
+    constants::TIMETABLE_EXEMPTIONS_FILE,
+    file_stuff::write_json_atomic,
+    types::{CdAuthData, ResponseError, TimetableExemptions},
+};
+
+const MAX_MODULES: usize = 100;
+const MAX_MODULE_LEN: usize = 200;
+
+lazy_static! {
+    static ref EXEMPTIONS: Mutex<HashMap<String, Vec<String>>> = Mutex::new(HashMap::new());
+}
+
+fn exemptions_file() -> Result<&'static str, ResponseError> {
+    TIMETABLE_EXEMPTIONS_FILE
+        .get()
+        .and_then(|file| file.as_deref())
+        .ok_or(ResponseError {
+            message: "Timetable exemptions are not enabled on this server".to_string(),
+            status_code: StatusCode::NOT_IMPLEMENTED,
+        })
+}
+
+// loads the persisted exemptions, no-op if they are disabled
+pub async fn start() {
+    let Ok(file) = exemptions_file() else {
+        return;
+    };
+
+    let exemptions: HashMap<String, Vec<String>> = match fs::read_to_string(file) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::error!("Timetable exemptions: unable to parse {file}: {e}, starting empty");
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    };
+
+    log::info!(
+        "Timetable exemptions: {} user(s) loaded from {file}",
+        exemptions.len()
+    );
+    *EXEMPTIONS.lock().await = exemptions;
+}
+
+fn persist(exemptions: &HashMap<String, Vec<String>>) {
+    let Ok(file) = exemptions_file() else {
+        return;
+    };
+
+    if let Err(e) = write_json_atomic(file, exemptions) {
+        log::error!("Timetable exemptions: unable to persist exemptions: {e}");
+    }
+}
+
+// empty if the feature is disabled, the timetable just isn't filtered then
+pub async fn user_exemptions(user: &str) -> Vec<String> {
+    EXEMPTIONS
+        .lock()
+        .await
+        .get(user)
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub async fn get_timetable_exemptions(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+) -> Result<Json<TimetableExemptions>, ResponseError> {
+    exemptions_file()?;
+
+    Ok(Json(TimetableExemptions {
+        modules: user_exemptions(&cd_auth_data.user).await,
+    }))
+}
+
+// replaces the list of the user, an empty list deletes it
+pub async fn post_timetable_exemptions(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(exemptions_request): Json<TimetableExemptions>,
+) -> Result<Json<TimetableExemptions>, ResponseError> {
+    exemptions_file()?;
+
+    let mut modules: Vec<String> = Vec::new();
+    for module in exemptions_request.modules {
+        let module = module.trim().to_string();
+        if !module.is_empty()
+            && !modules
+                .iter()
+                .any(|other| other.eq_ignore_ascii_case(&module))
+        {
+            modules.push(module);
+        }
+    }
+
+    if modules.len() > MAX_MODULES || modules.iter().any(|module| module.len() > MAX_MODULE_LEN) {
+        return Err(ResponseError {
+            message: format!(
+                "Maximal {MAX_MODULES} Module mit je höchstens {MAX_MODULE_LEN} Zeichen"
+            ),
+            status_code: StatusCode::BAD_REQUEST,
+        });
+    }
+
+    let mut exemptions = EXEMPTIONS.lock().await;
+    if modules.is_empty() {
+        exemptions.remove(&cd_auth_data.user);
+    } else {
+        exemptions.insert(cd_auth_data.user, modules.clone());
+    }
+    persist(&exemptions);
+
+    Ok(Json(TimetableExemptions { modules }))
+}
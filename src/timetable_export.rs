@@ -0,0 +1,441 @@
+// Week view of the timetable as PDF or PNG. Both are drawn by the same layout code through
+// WeekCanvas, only text rendering differs (PDF standard font vs. PNG bitmap font).
+
+use axum::{
+    extract::Query,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Timelike};
+use chrono_tz::Tz;
+use http::StatusCode;
+
+use crate::{
+    campus_backend::req_client_funcs::get_client_default,
+    color_stuff::ColorScheme,
+    pdf_stuff::{approx_text_width, PdfDocument, PdfFont, PdfPage, A4_HEIGHT, A4_WIDTH},
+    png_stuff::PngCanvas,
+    stundenplan::{fetch_timetable, stundenplan_range},
+    time_stuff::current_week_berlin,
+    types::{
+        CdAuthData, ResponseError, StundenplanQuery, TimetableEvent, TimetableEventStatus,
+        TimetableExportFormat, TimetableExportQuery,
+    },
+};
+
+const DEFAULT_PNG_SIZE: (u32, u32) = (1920, 1080);
+const MIN_SIZE: u32 = 200;
+const MAX_PDF_SIZE: u32 = 2400;
+const MAX_PNG_SIZE: u32 = 4096;
+// shown even if the week has no lectures that early / late
+const DEFAULT_FIRST_HOUR: u32 = 8;
+const DEFAULT_LAST_HOUR: u32 = 18;
+
+const WHITE: (u8, u8, u8) = (255, 255, 255);
+const BLACK: (u8, u8, u8) = (0, 0, 0);
+const GREY: (u8, u8, u8) = (110, 110, 110);
+const GRID: (u8, u8, u8) = (220, 220, 220);
+const CANCELLED: (u8, u8, u8) = (200, 200, 200);
+
+const WEEKDAYS: [&str; 7] = [
+    "Montag",
+    "Dienstag",
+    "Mittwoch",
+    "Donnerstag",
+    "Freitag",
+    "Samstag",
+    "Sonntag",
+];
+
+pub async fn get_timetable_export(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Query(export_query): Query<TimetableExportQuery>,
+) -> Result<Response, ResponseError> {
+    let (default_size, max_size) = match export_query.format {
+        TimetableExportFormat::Pdf => ((A4_HEIGHT as u32, A4_WIDTH as u32), MAX_PDF_SIZE),
+        TimetableExportFormat::Png => (DEFAULT_PNG_SIZE, MAX_PNG_SIZE),
+    };
+    let width = export_query.width.unwrap_or(default_size.0);
+    let height = export_query.height.unwrap_or(default_size.1);
+    if !(MIN_SIZE..=max_size).contains(&width) || !(MIN_SIZE..=max_size).contains(&height) {
+        return Err(ResponseError {
+            message: format!("width und height müssen zwischen {MIN_SIZE} und {max_size} liegen"),
+            status_code: StatusCode::BAD_REQUEST,
+        });
+    }
+
+    let monday = match export_query.week {
+        Some(day) => day - Duration::days(day.weekday().num_days_from_monday() as i64),
+        None => current_week_berlin().0,
+    };
+    let stundenplan_query = StundenplanQuery {
+        start: Some(monday),
+        end: Some(monday + Duration::days(6)),
+        palette: export_query.palette,
+        colors: export_query.colors.clone(),
+        ..Default::default()
+    };
+    let (start, end) = stundenplan_range(&stundenplan_query)?;
+    let color_scheme = ColorScheme::from_query(&stundenplan_query)?;
+
+    let client = get_client_default(true)?;
+    let events = fetch_timetable(&client, &cd_auth_data, &start, &end, &color_scheme).await?;
+
+    let (width, height) = (width as f32, height as f32);
+    let (content_type, extension, body) = match export_query.format {
+        TimetableExportFormat::Pdf => {
+            let mut page = PdfPage::new(width, height);
+            draw_week(
+                &mut page,
+                width,
+                height,
+                monday,
+                &events,
+                export_query.weekend,
+            );
+            let mut document = PdfDocument::default();
+            document.add_page(page);
+            ("application/pdf", "pdf", document.render())
+        }
+        TimetableExportFormat::Png => {
+            let mut canvas = PngCanvas::new(width as u32, height as u32, WHITE);
+            draw_week(
+                &mut canvas,
+                width,
+                height,
+                monday,
+                &events,
+                export_query.weekend,
+            );
+            ("image/png", "png", canvas.render()?)
+        }
+    };
+
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"stundenplan-{}.{extension}\"",
+                    monday.format("%G-KW%V")
+                ),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+trait WeekCanvas {
+    fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, rgb: (u8, u8, u8));
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32, rgb: (u8, u8, u8));
+    // y is the baseline
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, rgb: (u8, u8, u8), text: &str);
+    fn text_width(&self, text: &str, size: f32) -> f32;
+
+    fn fitted_text(&self, text: &str, size: f32, max_width: f32) -> String {
+        if self.text_width(text, size) <= max_width {
+            return text.to_string();
+        }
+        let mut fitted = String::new();
+        for c in text.chars() {
+            fitted.push(c);
+            if self.text_width(&fitted, size) + self.text_width("..", size) > max_width {
+                fitted.pop();
+                break;
+            }
+        }
+        fitted + ".."
+    }
+}
+
+impl WeekCanvas for PdfPage {
+    fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, rgb: (u8, u8, u8)) {
+        PdfPage::fill_rect(self, x, y, w, h, rgb);
+    }
+
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32, rgb: (u8, u8, u8)) {
+        PdfPage::line(self, x1, y1, x2, y2, width, rgb);
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, rgb: (u8, u8, u8), text: &str) {
+        let font = if bold {
+            PdfFont::Bold
+        } else {
+            PdfFont::Regular
+        };
+        PdfPage::text(self, x, y, size, font, rgb, text);
+    }
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        approx_text_width(text, size)
+    }
+}
+
+impl WeekCanvas for PngCanvas {
+    fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, rgb: (u8, u8, u8)) {
+        PngCanvas::fill_rect(self, x, y, w, h, rgb);
+    }
+
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32, rgb: (u8, u8, u8)) {
+        PngCanvas::line(self, x1, y1, x2, y2, width, rgb);
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, rgb: (u8, u8, u8), text: &str) {
+        PngCanvas::text(self, x, y, size, bold, rgb, text);
+    }
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        PngCanvas::text_width(text, size)
+    }
+}
+
+fn draw_week(
+    canvas: &mut impl WeekCanvas,
+    width: f32,
+    height: f32,
+    monday: NaiveDate,
+    events: &[TimetableEvent],
+    weekend: bool,
+) {
+    let margin = width.min(height) * 0.03;
+    let title_size = (height * 0.035).clamp(10.0, 48.0);
+    let label_size = (height * 0.022).clamp(7.0, 28.0);
+    let event_size = (height * 0.018).clamp(6.0, 24.0);
+
+    // weekends only show up when asked for or when something happens there
+    let day_count = if weekend
+        || events
+            .iter()
+            .any(|event| event.start.weekday().num_days_from_monday() >= 5)
+    {
+        7
+    } else {
+        5
+    };
+
+    let timed = events
+        .iter()
+        .filter(|event| !event.all_day)
+        .collect::<Vec<_>>();
+    let first_hour = timed
+        .iter()
+        .map(|event| event.start.hour())
+        .min()
+        .unwrap_or(DEFAULT_FIRST_HOUR)
+        .min(DEFAULT_FIRST_HOUR);
+    let last_hour = timed
+        .iter()
+        .map(|event| event.end.hour() + (event.end.minute() > 0) as u32)
+        .max()
+        .unwrap_or(DEFAULT_LAST_HOUR)
+        .clamp(DEFAULT_LAST_HOUR, 24);
+
+    let sunday = monday + Duration::days(6);
+    canvas.text(
+        margin,
+        margin + title_size,
+        title_size,
+        true,
+        BLACK,
+        &format!(
+            "Stundenplan KW {} ({} - {})",
+            monday.iso_week().week(),
+            monday.format("%d.%m."),
+            sunday.format("%d.%m.%Y")
+        ),
+    );
+
+    let axis_width = canvas.text_width("00:00", label_size) + margin * 0.5;
+    let grid_left = margin + axis_width;
+    let grid_right = width - margin;
+    let header_top = margin + title_size * 1.6;
+    let grid_top = header_top + label_size * 3.2;
+    let grid_bottom = height - margin;
+    let column_width = (grid_right - grid_left) / day_count as f32;
+    let hour_height = (grid_bottom - grid_top) / (last_hour - first_hour) as f32;
+
+    let y_of = |hour: f32| grid_top + (hour - first_hour as f32) * hour_height;
+
+    for hour in first_hour..=last_hour {
+        let y = y_of(hour as f32);
+        canvas.line(grid_left, y, grid_right, y, 0.5, GRID);
+        if hour < last_hour {
+            canvas.text(
+                margin,
+                y + label_size,
+                label_size,
+                false,
+                GREY,
+                &format!("{hour:02}:00"),
+            );
+        }
+    }
+
+    for (day, weekday) in WEEKDAYS.iter().enumerate().take(day_count) {
+        let date = monday + Duration::days(day as i64);
+        let x = grid_left + day as f32 * column_width;
+        canvas.line(x, header_top, x, grid_bottom, 0.5, GRID);
+        canvas.text(
+            x + label_size * 0.3,
+            header_top + label_size,
+            label_size,
+            true,
+            BLACK,
+            &canvas.fitted_text(
+                &format!("{weekday} {}", date.format("%d.%m.")),
+                label_size,
+                column_width - label_size * 0.6,
+            ),
+        );
+
+        // all day events (holidays, practical phases) go below the day name
+        let all_day = events
+            .iter()
+            .filter(|event| {
+                event.all_day && event.start.date_naive() <= date && event.end.date_naive() >= date
+            })
+            .map(|event| event.title.trim())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if !all_day.is_empty() {
+            canvas.text(
+                x + label_size * 0.3,
+                header_top + label_size * 2.3,
+                label_size * 0.85,
+                false,
+                GREY,
+                &canvas.fitted_text(&all_day, label_size * 0.85, column_width - label_size * 0.6),
+            );
+        }
+
+        let day_events = timed
+            .iter()
+            .filter(|event| event.start.date_naive() == date)
+            .copied()
+            .collect::<Vec<_>>();
+        for (event, lane, lanes) in assign_lanes(&day_events) {
+            let lane_width = column_width / lanes as f32;
+            let box_x = x + lane as f32 * lane_width + 1.5;
+            let box_w = lane_width - 3.0;
+            let top = y_of(event.start.hour() as f32 + event.start.minute() as f32 / 60.0);
+            let bottom =
+                y_of(event.end.hour() as f32 + event.end.minute() as f32 / 60.0).min(grid_bottom);
+            draw_event(
+                canvas,
+                event,
+                box_x,
+                top + 1.0,
+                box_w,
+                bottom - top - 2.0,
+                event_size,
+            );
+        }
+    }
+    canvas.line(grid_right, header_top, grid_right, grid_bottom, 0.5, GRID);
+}
+
+fn draw_event(
+    canvas: &mut impl WeekCanvas,
+    event: &TimetableEvent,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    size: f32,
+) {
+    let cancelled = event.status == TimetableEventStatus::Cancelled;
+    let (background, foreground) = if cancelled {
+        (CANCELLED, GREY)
+    } else {
+        (hex_to_rgb(&event.color), hex_to_rgb(&event.font_color))
+    };
+    canvas.fill_rect(x, y, w, h, background);
+
+    let title = if cancelled {
+        format!("(entfällt) {}", event.title)
+    } else {
+        event.title.clone()
+    };
+    let lines = [
+        (
+            format!(
+                "{} - {}",
+                event.start.format("%H:%M"),
+                event.end.format("%H:%M")
+            ),
+            false,
+        ),
+        (title, true),
+        (
+            event
+                .room
+                .as_ref()
+                .map(|room| room.short.clone())
+                .unwrap_or_default(),
+            false,
+        ),
+        (event.instructors_short.join(", "), false),
+    ];
+
+    // as many lines as fit into the box
+    let padding = size * 0.3;
+    let line_height = size * 1.2;
+    let mut baseline = y + padding + size;
+    for (text, bold) in lines.iter().filter(|(text, _)| !text.is_empty()) {
+        if baseline > y + h - padding * 0.5 {
+            break;
+        }
+        let fitted = canvas.fitted_text(text, size, w - 2.0 * padding);
+        canvas.text(x + padding, baseline, size, *bold, foreground, &fitted);
+        baseline += line_height;
+    }
+}
+
+// parallel events share the column, each gets a lane within its group of overlapping events
+fn assign_lanes<'a>(events: &[&'a TimetableEvent]) -> Vec<(&'a TimetableEvent, usize, usize)> {
+    let mut events = events.to_vec();
+    events.sort_by_key(|event| (event.start, event.end));
+
+    let mut placed = Vec::new();
+    let mut group: Vec<(&TimetableEvent, usize)> = Vec::new();
+    let mut lane_ends = Vec::new();
+    let mut group_end = None;
+
+    let mut flush = |group: &mut Vec<(&'a TimetableEvent, usize)>, lanes: usize| {
+        placed.extend(group.drain(..).map(|(event, lane)| (event, lane, lanes)));
+    };
+
+    for event in events {
+        if group_end.is_some_and(|end| event.start >= end) {
+            flush(&mut group, lane_ends.len());
+            lane_ends.clear();
+        }
+        let lane = match lane_ends.iter().position(|end| *end <= event.start) {
+            Some(lane) => {
+                lane_ends[lane] = event.end;
+                lane
+            }
+            None => {
+                lane_ends.push(event.end);
+                lane_ends.len() - 1
+            }
+        };
+        group.push((event, lane));
+        group_end = Some(group_end.map_or(event.end, |end: DateTime<Tz>| end.max(event.end)));
+    }
+    flush(&mut group, lane_ends.len());
+
+    placed
+}
+
+// the colors come from color_stuff and are always "#RRGGBB"
+fn hex_to_rgb(hex: &str) -> (u8, u8, u8) {
+    let channel = |range| u8::from_str_radix(hex.trim_start_matches('#').get(range)?, 16).ok();
+    match (channel(0..2), channel(2..4), channel(4..6)) {
+        (Some(r), Some(g), Some(b)) => (r, g, b),
+        _ => GREY,
+    }
+}
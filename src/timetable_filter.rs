@@ -0,0 +1,153 @@
+use http::StatusCode;
+
+use crate::types::{
+    ResponseError, StundenplanQuery, TimetableEvent, TimetableEventStatus, TimetableEventType,
+};
+
+// parsed filters of a StundenplanQuery, all terms are lowercase
+#[derive(Debug, Default)]
+pub struct TimetableFilter {
+    modules: Vec<String>,
+    exclude_modules: Vec<String>,
+    // persisted exemptions, unlike the query terms matched exactly
+    exempt_modules: Vec<String>,
+    instructors: Vec<String>,
+    exclude_instructors: Vec<String>,
+    rooms: Vec<String>,
+    exclude_rooms: Vec<String>,
+    types: Vec<TimetableEventType>,
+    exclude_types: Vec<TimetableEventType>,
+}
+
+impl TimetableFilter {
+    pub fn from_query(query: &StundenplanQuery, exempt: &[String]) -> Result<Self, ResponseError> {
+        let exempt_modules = if query.show_exempt {
+            Vec::new()
+        } else {
+            exempt
+                .iter()
+                .map(|module| module.trim().to_lowercase())
+                .collect()
+        };
+
+        Ok(TimetableFilter {
+            modules: split_terms(query.modules.as_deref()),
+            exclude_modules: split_terms(query.exclude_modules.as_deref()),
+            exempt_modules,
+            instructors: split_terms(query.instructors.as_deref()),
+            exclude_instructors: split_terms(query.exclude_instructors.as_deref()),
+            rooms: split_terms(query.rooms.as_deref()),
+            exclude_rooms: split_terms(query.exclude_rooms.as_deref()),
+            types: parse_types(query.types.as_deref())?,
+            exclude_types: parse_types(query.exclude_types.as_deref())?,
+        })
+    }
+
+    pub fn apply(&self, events: Vec<TimetableEvent>) -> Vec<TimetableEvent> {
+        events
+            .into_iter()
+            .filter(|event| self.matches(event))
+            .collect()
+    }
+
+    fn matches(&self, event: &TimetableEvent) -> bool {
+        let event_type = event_type(event);
+
+        included(&self.modules, &self.exclude_modules, |term| {
+            matches_module(event, term)
+        }) && !self
+            .exempt_modules
+            .iter()
+            .any(|module| is_module(event, module))
+            && included(&self.instructors, &self.exclude_instructors, |term| {
+                matches_instructor(event, term)
+            })
+            && included(&self.rooms, &self.exclude_rooms, |term| {
+                matches_room(event, term)
+            })
+            && (self.types.is_empty() || self.types.contains(&event_type))
+            && !self.exclude_types.contains(&event_type)
+    }
+}
+
+// an empty include list lets everything through, excludes always win
+fn included(include: &[String], exclude: &[String], matches: impl Fn(&str) -> bool) -> bool {
+    (include.is_empty() || include.iter().any(|term| matches(term)))
+        && !exclude.iter().any(|term| matches(term))
+}
+
+fn split_terms(terms: Option<&str>) -> Vec<String> {
+    terms
+        .unwrap_or_default()
+        .split(',')
+        .map(|term| term.trim().to_lowercase())
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+fn parse_types(types: Option<&str>) -> Result<Vec<TimetableEventType>, ResponseError> {
+    split_terms(types)
+        .iter()
+        .map(|event_type| match event_type.as_str() {
+            "lecture" => Ok(TimetableEventType::Lecture),
+            "exam" => Ok(TimetableEventType::Exam),
+            "cancelled" => Ok(TimetableEventType::Cancelled),
+            _ => Err(ResponseError {
+                message: format!(
+                    "Unbekannter Typ \"{event_type}\" (erlaubt: lecture, exam, cancelled)"
+                ),
+                status_code: StatusCode::BAD_REQUEST,
+            }),
+        })
+        .collect()
+}
+
+// CampusDual has no event type, and darkred (highlighted) marks short notice changes as well as
+// exams, so only the title and description tell exams apart
+fn event_type(event: &TimetableEvent) -> TimetableEventType {
+    const EXAM: [&str; 4] = ["prüfung", "klausur", "exam", "testat"];
+
+    if event.status == TimetableEventStatus::Cancelled {
+        return TimetableEventType::Cancelled;
+    }
+    let text = format!("{} {}", event.title, event.description).to_lowercase();
+    if EXAM.iter().any(|keyword| text.contains(keyword)) {
+        TimetableEventType::Exam
+    } else {
+        TimetableEventType::Lecture
+    }
+}
+
+// the code has to match exactly, titles partially ("mathe" matches "Mathematik I")
+fn matches_module(event: &TimetableEvent, term: &str) -> bool {
+    event
+        .module_code
+        .as_ref()
+        .is_some_and(|code| code.to_lowercase() == term)
+        || event.module_title.to_lowercase().contains(term)
+        || event.title.to_lowercase().contains(term)
+}
+
+// exemptions hide a whole module, so "MA" must not hide "Mathematik"
+fn is_module(event: &TimetableEvent, module: &str) -> bool {
+    event
+        .module_code
+        .as_ref()
+        .is_some_and(|code| code.to_lowercase() == module)
+        || event.module_title.to_lowercase() == module
+        || event.title.to_lowercase() == module
+}
+
+fn matches_instructor(event: &TimetableEvent, term: &str) -> bool {
+    event
+        .instructors
+        .iter()
+        .chain(&event.instructors_short)
+        .any(|instructor| instructor.to_lowercase().contains(term))
+}
+
+fn matches_room(event: &TimetableEvent, term: &str) -> bool {
+    event.room.as_ref().is_some_and(|room| {
+        room.short.to_lowercase().contains(term) || room.long.to_lowercase().contains(term)
+    })
+}
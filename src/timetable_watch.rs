@@ -0,0 +1,535 @@
+// Opt-in (TIMETABLE_WATCH_FILE) background check of the timetable. Changes within the next days
+// are sent to a webhook and/or by mail (SMTP_URL). Watches are persisted as JSON, credentials AES encrypted.
+
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use axum::{Extension, Json};
+use chrono::{DateTime, FixedOffset, Utc};
+use http::StatusCode;
+use lazy_static::lazy_static;
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    campus_backend::{login::cdlogin_get_jcookie_and_meta, req_client_funcs::get_client_default},
+    constants::{SMTP_FROM, SMTP_URL, TIMETABLE_WATCH_FILE, TIMETABLE_WATCH_INTERVAL_SEC},
+    encryption::{decrypt_login_data, encrypt_login_data},
+    file_stuff::write_json_atomic,
+    group_timetable_cache::invalidate_group,
+    timetable_changes::{
+        change_days, comparable_snapshots, describe_change, diff_snapshots, fetch_snapshot,
+    },
+    types::{
+        CdAuthData, ResponseError, TimetableChange, TimetableSnapshotEntry, TimetableWatchRequest,
+    },
+};
+
+const WORKER_INTERVAL: Duration = Duration::from_secs(60);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref WATCHES: Mutex<Vec<TimetableWatch>> = Mutex::new(Vec::new());
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TimetableWatch {
+    user: String,
+    days: i64,
+    webhook_url: Option<String>,
+    email: Option<String>,
+    created_at: DateTime<Utc>,
+    last_checked: Option<DateTime<Utc>>,
+    last_notified: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    snapshot: Vec<TimetableSnapshotEntry>,
+    // watches from before the horizon was stored fall back to the last lecture
+    #[serde(default)]
+    horizon: Option<DateTime<FixedOffset>>,
+    // AES encrypted CampusLoginData, the watcher has to log in by itself
+    nonce: String,
+    cipher: String,
+}
+
+// what users get to see of their watch (no credentials)
+#[derive(Serialize, Debug)]
+pub struct TimetableWatchInfo {
+    pub days: i64,
+    pub webhook_url: Option<String>,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub last_notified: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl From<&TimetableWatch> for TimetableWatchInfo {
+    fn from(watch: &TimetableWatch) -> Self {
+        TimetableWatchInfo {
+            days: watch.days,
+            webhook_url: watch.webhook_url.clone(),
+            email: watch.email.clone(),
+            created_at: watch.created_at,
+            last_checked: watch.last_checked,
+            last_notified: watch.last_notified,
+            last_error: watch.last_error.clone(),
+        }
+    }
+}
+
+// body of the webhook POST
+#[derive(Serialize, Debug)]
+struct TimetableWatchNotification<'a> {
+    user: &'a str,
+    changes: &'a [TimetableChange],
+    summary: Vec<String>,
+}
+
+fn watch_file() -> Result<&'static str, ResponseError> {
+    TIMETABLE_WATCH_FILE
+        .get()
+        .and_then(|file| file.as_deref())
+        .ok_or(ResponseError {
+            message: "Timetable watch is not enabled on this server".to_string(),
+            status_code: StatusCode::NOT_IMPLEMENTED,
+        })
+}
+
+// loads the persisted watches and spawns the worker, no-op if the watcher is disabled
+pub async fn start() {
+    let Ok(file) = watch_file() else {
+        return;
+    };
+
+    let watches: Vec<TimetableWatch> = match fs::read_to_string(file) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::error!("Timetable watch: unable to parse {file}: {e}, starting empty");
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+
+    log::info!(
+        "Timetable watch: {} watch(es) loaded from {file}",
+        watches.len()
+    );
+    *WATCHES.lock().await = watches;
+
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(WORKER_INTERVAL).await;
+            check_due_watches().await;
+        }
+    });
+}
+
+fn persist(watches: &[TimetableWatch]) {
+    let Ok(file) = watch_file() else {
+        return;
+    };
+
+    if let Err(e) = write_json_atomic(file, watches) {
+        log::error!("Timetable watch: unable to persist watches: {e}");
+    }
+}
+
+// creates or replaces the watch of the user, the current timetable is the baseline
+pub async fn post_timetable_watch(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+    Json(watch_request): Json<TimetableWatchRequest>,
+) -> Result<Json<TimetableWatchInfo>, ResponseError> {
+    watch_file()?;
+
+    let days = change_days(watch_request.days)?;
+    let webhook_url = watch_request
+        .webhook_url
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+    let email = watch_request
+        .email
+        .map(|email| email.trim().to_string())
+        .filter(|email| !email.is_empty());
+
+    let bad_request = |message: &str| ResponseError {
+        message: message.to_string(),
+        status_code: StatusCode::BAD_REQUEST,
+    };
+    if webhook_url.is_none() && email.is_none() {
+        return Err(bad_request("webhook_url or email is required"));
+    }
+    if let Some(url) = &webhook_url {
+        resolve_webhook(url)
+            .await
+            .map_err(|message| bad_request(&message))?;
+    }
+    if let Some(email) = &email {
+        if smtp_url().is_none() {
+            return Err(ResponseError {
+                message: "Email notifications are not enabled on this server".to_string(),
+                status_code: StatusCode::NOT_IMPLEMENTED,
+            });
+        }
+        if email.parse::<Mailbox>().is_err() {
+            return Err(bad_request("email is not a valid address"));
+        }
+    }
+
+    let client = get_client_default(true)?;
+    let (snapshot, horizon) = fetch_snapshot(&client, &cd_auth_data, days).await?;
+    let (nonce, cipher) = encrypt_login_data(&cd_auth_data)?;
+
+    let now = Utc::now();
+    let watch = TimetableWatch {
+        user: cd_auth_data.user,
+        days,
+        webhook_url,
+        email,
+        created_at: now,
+        last_checked: Some(now),
+        last_notified: None,
+        last_error: None,
+        snapshot,
+        horizon: Some(horizon),
+        nonce,
+        cipher,
+    };
+    let info = TimetableWatchInfo::from(&watch);
+
+    let mut watches = WATCHES.lock().await;
+    watches.retain(|other| other.user != watch.user);
+    watches.push(watch);
+    persist(&watches);
+
+    Ok(Json(info))
+}
+
+pub async fn get_timetable_watch(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+) -> Result<Json<Option<TimetableWatchInfo>>, ResponseError> {
+    watch_file()?;
+
+    let watches = WATCHES.lock().await;
+    Ok(Json(
+        watches
+            .iter()
+            .find(|watch| watch.user == cd_auth_data.user)
+            .map(TimetableWatchInfo::from),
+    ))
+}
+
+pub async fn post_delete_timetable_watch(
+    Extension(cd_auth_data): Extension<CdAuthData>,
+) -> Result<Json<TimetableWatchInfo>, ResponseError> {
+    watch_file()?;
+
+    let mut watches = WATCHES.lock().await;
+    let position = watches
+        .iter()
+        .position(|watch| watch.user == cd_auth_data.user)
+        .ok_or(ResponseError {
+            message: "No timetable watch found".to_string(),
+            status_code: StatusCode::NOT_FOUND,
+        })?;
+
+    let watch = watches.remove(position);
+    persist(&watches);
+
+    Ok(Json(TimetableWatchInfo::from(&watch)))
+}
+
+async fn check_due_watches() {
+    let interval = chrono::Duration::seconds(*TIMETABLE_WATCH_INTERVAL_SEC.get().unwrap() as i64);
+
+    // don't hold the lock during the (slow) CampusDual calls
+    let due_watches = {
+        let watches = WATCHES.lock().await;
+        let now = Utc::now();
+        watches
+            .iter()
+            .filter(|watch| {
+                watch
+                    .last_checked
+                    .is_none_or(|last_checked| last_checked + interval <= now)
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    // one after another, no need to hammer CampusDual
+    for watch in due_watches {
+        let check = check_watch(&watch).await;
+
+        let mut watches = WATCHES.lock().await;
+        // the watch might have been replaced or deleted in the meantime
+        let Some(stored_watch) = watches
+            .iter_mut()
+            .find(|stored| stored.user == watch.user && stored.created_at == watch.created_at)
+        else {
+            continue;
+        };
+
+        stored_watch.last_checked = Some(Utc::now());
+        match check {
+            Ok((snapshot, horizon, notified)) => {
+                stored_watch.snapshot = snapshot;
+                stored_watch.horizon = Some(horizon);
+                stored_watch.last_error = None;
+                if notified {
+                    stored_watch.last_notified = stored_watch.last_checked;
+                }
+            }
+            Err(message) => {
+                log::warn!("Timetable watch: check failed: {message}");
+                stored_watch.last_error = Some(message);
+            }
+        }
+        persist(&watches);
+    }
+}
+
+// returns the new snapshot, its horizon and whether a notification went out
+async fn check_watch(
+    watch: &TimetableWatch,
+) -> Result<(Vec<TimetableSnapshotEntry>, DateTime<FixedOffset>, bool), String> {
+    let login_data = decrypt_login_data(&watch.nonce, &watch.cipher)
+        .ok_or("Stored credentials are unreadable")?;
+    let (cd_auth_data, _) = cdlogin_get_jcookie_and_meta(login_data)
+        .await
+        .map_err(|e| format!("CampusDual login failed: {e}"))?;
+
+    let client = get_client_default(true).map_err(|e| e.to_string())?;
+    let (snapshot, horizon) = fetch_snapshot(&client, &cd_auth_data, watch.days)
+        .await
+        .map_err(|e| e.message)?;
+
+    let (previous, current) =
+        comparable_snapshots(&watch.snapshot, watch.horizon, &snapshot, horizon);
+    let changes = diff_snapshots(&previous, &current);
+    if changes.is_empty() {
+        return Ok((snapshot, horizon, false));
+    }
+    invalidate_group(&cd_auth_data.seminar_group);
+
+    // the snapshot is only advanced once the user actually heard about the changes
+    notify(watch, &changes).await?;
+    Ok((snapshot, horizon, true))
+}
+
+async fn notify(watch: &TimetableWatch, changes: &[TimetableChange]) -> Result<(), String> {
+    let summary = changes.iter().map(describe_change).collect::<Vec<_>>();
+
+    if let Some(webhook_url) = &watch.webhook_url {
+        // checked again on every call, the host might resolve differently by now
+        let (host, addr) = resolve_webhook(webhook_url)
+            .await
+            .map_err(|e| format!("Webhook failed: {e}"))?;
+        reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            // the checked address is used, and redirects could lead anywhere
+            .resolve(&host, addr)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| e.to_string())?
+            .post(webhook_url)
+            .json(&TimetableWatchNotification {
+                user: &watch.user,
+                changes,
+                summary: summary.clone(),
+            })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Webhook failed: {e}"))?;
+    }
+
+    if let Some(email) = &watch.email {
+        send_mail(email, &summary)
+            .await
+            .map_err(|e| format!("Email failed: {e}"))?;
+    }
+
+    Ok(())
+}
+
+// Webhooks are POSTed by the server, so they must not reach anything internal (SSRF).
+// Returns the host and the (public) address it resolved to.
+async fn resolve_webhook(url: &str) -> Result<(String, SocketAddr), String> {
+    let url = reqwest::Url::parse(url).map_err(|_| "webhook_url is not a valid URL")?;
+    if url.scheme() != "https" {
+        return Err("webhook_url must be a https URL".to_string());
+    }
+    let host = url.host_str().ok_or("webhook_url has no host")?.to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addrs = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await
+        .map_err(|_| format!("webhook host {host} could not be resolved"))?
+        .collect::<Vec<_>>();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(format!("webhook host {host} is not a public address"));
+    }
+
+    Ok((host, addrs[0]))
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // 0.0.0.0/8, shared address space (carrier-grade NAT), IETF protocol
+                // assignments, benchmarking and reserved 240.0.0.0/4
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let octets = ip.octets();
+            let embedded_v4 = |offset: usize| {
+                Ipv4Addr::new(
+                    octets[offset],
+                    octets[offset + 1],
+                    octets[offset + 2],
+                    octets[offset + 3],
+                )
+            };
+
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            // NAT64 64:ff9b::/96 and 6to4 2002::/16 reach the embedded IPv4 address
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return is_public_ip(IpAddr::V4(embedded_v4(12)));
+            }
+            if segments[0] == 0x2002 {
+                return is_public_ip(IpAddr::V4(embedded_v4(2)));
+            }
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // IPv4-compatible ::/96, local-use NAT64 64:ff9b:1::/48, discard 100::/64
+                || segments[..6] == [0; 6]
+                || segments[..3] == [0x64, 0xff9b, 1]
+                || segments[..4] == [0x100, 0, 0, 0]
+                // Teredo 2001::/32 and documentation 2001:db8::/32
+                || segments[..2] == [0x2001, 0]
+                || segments[..2] == [0x2001, 0xdb8]
+                // unique local fc00::/7 and link-local fe80::/10
+                || (segments[0] & 0xfe00) == 0xfc00
+                || (segments[0] & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+fn smtp_url() -> Option<&'static str> {
+    SMTP_URL.get().and_then(|url| url.as_deref())
+}
+
+async fn send_mail(email: &str, summary: &[String]) -> anyhow::Result<()> {
+    let smtp_url = smtp_url().ok_or(anyhow::anyhow!("SMTP_URL is not set"))?;
+
+    let message = Message::builder()
+        .from(SMTP_FROM.get().unwrap().parse()?)
+        .to(email.parse()?)
+        .subject(format!("Stundenplanänderungen ({})", summary.len()))
+        .body(summary.join("\n"))?;
+
+    AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)?
+        .build()
+        .send(message)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_public(ip: &str) -> bool {
+        is_public_ip(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn allows_public_addresses() {
+        for ip in [
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700:4700::1111",
+            "64:ff9b::101:101",
+            "2002:101:101::1",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(is_public(ip), "{ip} should be allowed");
+        }
+    }
+
+    #[test]
+    fn rejects_internal_ipv4() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "192.0.0.8",
+            "192.0.2.1",
+            "198.18.0.1",
+            "198.19.255.255",
+            "240.0.0.1",
+            "255.255.255.255",
+            "224.0.0.1",
+        ] {
+            assert!(!is_public(ip), "{ip} should be rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_internal_ipv6() {
+        for ip in [
+            "::1",
+            "::",
+            "fe80::1",
+            "fd00::1",
+            "ff02::1",
+            "2001:db8::1",
+            "2001:0:4136:e378::1",
+            "100::1",
+            "64:ff9b:1::1",
+        ] {
+            assert!(!is_public(ip), "{ip} should be rejected");
+        }
+    }
+
+    #[test]
+    fn checks_embedded_ipv4() {
+        for ip in [
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "::127.0.0.1",
+            "::8.8.8.8",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::10.0.0.1",
+            "2002:a9fe:a9fe::1",
+            "2002:7f00:1::1",
+        ] {
+            assert!(!is_public(ip), "{ip} should be rejected");
+        }
+    }
+}
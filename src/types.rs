@@ -1,7 +1,13 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use regex::Regex;
 use reqwest::StatusCode;
 use scraper::ElementRef;
 use serde::{Deserialize, Serialize};
 
+use crate::room_directory::room_location;
+
 #[derive(Serialize, Deserialize)]
 pub struct CampusLoginData {
     pub username: String,
@@ -36,53 +42,305 @@ pub struct CdAuthData {
     pub hash: String,
     pub user: String,
     pub password: String,
+    // empty for sessions from before it was part of the token
+    #[serde(default)]
+    pub seminar_group: String,
 }
 
+// The raw strings are kept for compatibility, the *_parsed/_value/_date fields are typed versions
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CampusDualGrade {
     pub name: String,
     pub grade: String,
+    #[serde(default)]
+    pub grade_value: GradeValue,
     pub total_passed: Option<bool>,
     pub credit_points: i32,
     pub akad_period: String,
+    pub akad_period_parsed: Option<AcademicPeriod>,
     pub subgrades: Vec<CampusDualSubGrade>,
 }
 
+impl CampusDualGrade {
+    pub fn newest_bekanntgabe(&self) -> Option<NaiveDate> {
+        self.subgrades
+            .iter()
+            .filter_map(|subgrade| subgrade.bekanntgabe_date)
+            .max()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CampusDualSubGrade {
     pub name: String,
     pub grade: String,
+    #[serde(default)]
+    pub grade_value: GradeValue,
     pub passed: Option<bool>,
     pub beurteilung: String,
     pub bekanntgabe: String,
+    pub bekanntgabe_date: Option<NaiveDate>,
     pub wiederholung: Option<String>,
     pub akad_period: String,
+    pub akad_period_parsed: Option<AcademicPeriod>,
     pub internal_metadata: Option<SubGradeMetadata>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum GradeValue {
+    Numeric(f32),
+    Passed,
+    Failed,
+    #[default]
+    NotYetGraded,
+}
+
+impl GradeValue {
+    // CampusDual grades are either decimal-comma numbers ("1,3") or (not) passed remarks,
+    // the pass/fail icon decides for everything else
+    pub fn parse(raw: &str, passed: Option<bool>) -> Self {
+        let raw = raw.trim();
+
+        if let Ok(numeric) = raw.replace(',', ".").parse::<f32>() {
+            return GradeValue::Numeric(numeric);
+        }
+
+        let lowercase = raw.to_lowercase();
+        if lowercase.contains("nicht bestanden") || lowercase.contains("nicht ausreichend") {
+            return GradeValue::Failed;
+        }
+        if lowercase.contains("bestanden") || lowercase.contains("mit erfolg") {
+            return GradeValue::Passed;
+        }
+
+        match passed {
+            Some(true) => GradeValue::Passed,
+            Some(false) => GradeValue::Failed,
+            None => GradeValue::NotYetGraded,
+        }
+    }
+
+    pub fn numeric(&self) -> Option<f32> {
+        match self {
+            GradeValue::Numeric(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum AcademicTerm {
+    Summer,
+    Winter,
+}
+
+// The winter term "WS 2023/24" starts in `year` 2023
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AcademicPeriod {
+    pub year: i32,
+    pub term: AcademicTerm,
+}
+
+impl AcademicPeriod {
+    pub fn parse(raw: &str) -> Option<Self> {
+        lazy_static! {
+            static ref RE_TERM: Regex =
+                Regex::new(r"(?i)\b(WS|WiSe|Winter\w*|SS|SoSe|Sommer\w*)\b").unwrap();
+            static ref RE_YEAR: Regex = Regex::new(r"\b(\d{4})\b").unwrap();
+        };
+
+        let term = match RE_TERM.captures(raw)?.get(1)?.as_str().to_lowercase() {
+            term if term.starts_with('w') => AcademicTerm::Winter,
+            _ => AcademicTerm::Summer,
+        };
+        let year = RE_YEAR.captures(raw)?.get(1)?.as_str().parse().ok()?;
+
+        Some(AcademicPeriod { year, term })
+    }
+}
+
+// CampusDual dates are formatted as "12.03.2024"
+pub fn parse_cd_date(raw: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(raw.trim(), "%d.%m.%Y").ok()
+}
+
+// Query parameters of /get_grades, everything is optional
+#[derive(Deserialize, Debug, Default)]
+pub struct GradesQuery {
+    // "latest" for the newest academic period, otherwise e.g. "WS 2023/24"
+    pub period: Option<String>,
+    pub status: Option<GradeStatusFilter>,
+    pub search: Option<String>,
+    pub min_ects: Option<i32>,
+    pub sort: Option<GradesSortKey>,
+    pub order: Option<SortOrder>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GradeStatusFilter {
+    Passed,
+    Failed,
+    Pending,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GradesSortKey {
+    Date,
+    Name,
+    Grade,
+    Ects,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum GradesExportFormat {
+    Csv,
+    Jsonld,
+    Pdf,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GradesExportQuery {
+    pub format: GradesExportFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SubGradeMetadata {
     pub module: String,
     pub peryr: String,
     pub perid: String,
 }
 
+// Rooms come as "A 1.23", "B-204" or "5.101", the part before the first separator is the building
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExamRoom {
+    pub name: String,
+    pub building: Option<String>,
+    // from the room directory (ROOM_DIRECTORY_FILE)
+    pub location: Option<RoomLocation>,
+}
+
+impl ExamRoom {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let name = raw.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let building = name
+            .split_once([' ', '-', '.'])
+            .map(|(building, _)| building.trim().to_uppercase())
+            .filter(|building| !building.is_empty());
+
+        Some(ExamRoom {
+            name: name.to_string(),
+            building,
+            location: room_location(name),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ExamTableExtraction<T> {
+    pub options: Vec<T>,
+    pub warnings: Vec<ParserWarning>,
+}
+
+// Derived from the status icon CampusDual shows next to each exam
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExamStatus {
+    Missed,
+    Open,
+    Warning,
+    Registered,
+    Unknown { icon: String },
+}
+
+impl ExamStatus {
+    // matched on the file stem, CampusDual isn't consistent with paths and extensions
+    pub fn from_icon(icon: &str) -> Self {
+        let file_name = icon.rsplit('/').next().unwrap_or(icon);
+        let stem = file_name.split('.').next().unwrap_or(file_name);
+
+        match stem.to_lowercase().as_str() {
+            "missed" => ExamStatus::Missed,
+            "yellow" => ExamStatus::Open,
+            "exclamation" => ExamStatus::Warning,
+            "green" | "checked" => ExamStatus::Registered,
+            _ => ExamStatus::Unknown {
+                icon: icon.to_string(),
+            },
+        }
+    }
+
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            ExamStatus::Missed => "🚫",
+            ExamStatus::Open => "📝",
+            ExamStatus::Warning => "⚠️",
+            ExamStatus::Registered => "✅",
+            ExamStatus::Unknown { .. } => "⁉️",
+        }
+    }
+
+    pub fn label(&self) -> LocalizedLabel {
+        let (de, en) = match self {
+            ExamStatus::Missed => ("Verpasst", "Missed"),
+            ExamStatus::Open => ("Anmeldung möglich", "Open for registration"),
+            ExamStatus::Warning => ("Hinweis beachten", "Attention required"),
+            ExamStatus::Registered => ("Angemeldet", "Registered"),
+            ExamStatus::Unknown { .. } => ("Unbekannt", "Unknown"),
+        };
+
+        LocalizedLabel {
+            de: de.to_string(),
+            en: en.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LocalizedLabel {
+    pub de: String,
+    pub en: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CampusDualSignupOption {
     pub name: String,
     pub verfahren: String,
     pub pruefart: String,
+    // emoji, kept for older clients, use status_kind instead
     pub status: String,
+    pub status_kind: ExamStatus,
+    pub status_label: LocalizedLabel,
     pub signup_information: String,
     pub exam_date: Option<String>,
     pub exam_time: Option<String>,
     pub exam_room: Option<String>,
     pub warning_message: Option<String>,
     pub signup_until: Option<String>,
+    pub exam_date_parsed: Option<NaiveDate>,
+    pub exam_start: Option<NaiveTime>,
+    pub exam_end: Option<NaiveTime>,
+    pub exam_room_parsed: Option<ExamRoom>,
+    pub signup_until_date: Option<NaiveDate>,
     pub internal_metadata: Option<ExamRegistrationMetadata>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ExamRegistrationMetadata {
     pub assessment: String,
     pub peryr: String,
@@ -95,13 +353,21 @@ pub struct CampusDualVerfahrenOption {
     pub name: String,
     pub verfahren: String,
     pub pruefart: String,
+    // emoji, kept for older clients, use status_kind instead
     pub status: String,
+    pub status_kind: ExamStatus,
+    pub status_label: LocalizedLabel,
     pub signup_information: String,
     pub exam_date: Option<String>,
     pub exam_time: Option<String>,
     pub exam_room: Option<String>,
     pub warning_message: Option<String>,
     pub signoff_until: Option<String>,
+    pub exam_date_parsed: Option<NaiveDate>,
+    pub exam_start: Option<NaiveTime>,
+    pub exam_end: Option<NaiveTime>,
+    pub exam_room_parsed: Option<ExamRoom>,
+    pub signoff_until_date: Option<NaiveDate>,
     pub internal_metadata: Option<ExamRegistrationMetadata>,
 }
 
@@ -138,20 +404,20 @@ pub struct CdExamStats {
     pub ronmodus: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StundenplanItem {
     #[serde(rename = "allDay")]
-    all_day: bool,
+    pub all_day: bool,
     pub color: String,
     pub font_color: Option<String>,
-    description: String,
+    pub description: String,
     editable: bool,
     pub end: i64,
-    instructor: String,
-    remarks: String,
-    room: String,
-    sinstructor: String,
-    sroom: String,
+    pub instructor: String,
+    pub remarks: String,
+    pub room: String,
+    pub sinstructor: String,
+    pub sroom: String,
     pub start: i64,
     pub title: String,
 }
@@ -187,31 +453,31 @@ struct LatestReminder {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct UpcomingReminder {
+pub struct UpcomingReminder {
     #[serde(rename(deserialize = "BEGUZ"))]
-    beguz: String,
+    pub beguz: String,
     #[serde(rename(deserialize = "COMMENT"))]
-    comment: String,
+    pub comment: String,
     #[serde(rename(deserialize = "ENDUZ"))]
-    enduz: String,
+    pub enduz: String,
     #[serde(rename(deserialize = "EVDAT"))]
-    evdat: String,
+    pub evdat: String,
     #[serde(rename(deserialize = "INSTRUCTOR"))]
-    instructor: String,
+    pub instructor: String,
     #[serde(rename(deserialize = "LOCATION"))]
-    location: String,
+    pub location: String,
     #[serde(rename(deserialize = "OBJID"))]
-    objid: String,
+    pub objid: String,
     #[serde(rename(deserialize = "ROOM"))]
-    room: String,
+    pub room: String,
     #[serde(rename(deserialize = "SINSTRUCTOR"))]
-    sinstructor: String,
+    pub sinstructor: String,
     #[serde(rename(deserialize = "SM_SHORT"))]
-    sm_short: String,
+    pub sm_short: String,
     #[serde(rename(deserialize = "SM_STEXT"))]
-    sm_stext: String,
+    pub sm_stext: String,
     #[serde(rename(deserialize = "SROOM"))]
-    sroom: String,
+    pub sroom: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -225,7 +491,7 @@ pub struct CampusReminders {
     #[serde(rename(deserialize = "SEMESTER"))]
     semester: i64,
     #[serde(rename(deserialize = "UPCOMING"))]
-    upcoming: Vec<UpcomingReminder>,
+    pub upcoming: Vec<UpcomingReminder>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -265,7 +531,7 @@ pub struct ExportTimelineEvent {
     pub end: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct CdExamDetails {
     #[serde(rename(deserialize = "EV_AGRTYPE_TEXT"))]
     pub ev_agrtype_text: String,
@@ -318,6 +584,17 @@ pub struct CdExamDetails {
     pub ev_stext: String,
 }
 
+impl CdExamDetails {
+    // ev_reason and ev_continue_indicator depend on the requesting student
+    pub fn without_personal_data(&self) -> Self {
+        CdExamDetails {
+            ev_reason: String::new(),
+            ev_continue_indicator: String::new(),
+            ..self.clone()
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct CdGradeStatEntry {
     #[serde(rename(deserialize = "GRADETEXT"))]
@@ -335,6 +612,46 @@ pub struct GradeStatsAllStudents {
     pub ronmodus: i64,
 }
 
+// Per-exam result of /get_examdetails_batch, keyed by "assessment/peryr/perid/offerno"
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExamDetailsBatchEntry {
+    Details(Box<CdExamDetails>),
+    Error(String),
+}
+
+// Per-subgrade result of /get_gradestats_batch, keyed by "module/peryr/perid"
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradeStatsBatchEntry {
+    Stats(GradeStatsAllStudents),
+    Error(String),
+}
+
+// Something odd in a CampusDual table row that did not stop the rest from being parsed
+#[derive(Debug, Serialize, Clone)]
+pub struct ParserWarning {
+    pub row_id: Option<String>,
+    pub field: String,
+    pub reason: String,
+}
+
+impl ParserWarning {
+    pub fn new(row_id: Option<&str>, field: &str, reason: &str) -> Self {
+        ParserWarning {
+            row_id: row_id.map(|id| id.to_string()),
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GradesExtraction {
+    pub grades: Vec<CampusDualGrade>,
+    pub warnings: Vec<ParserWarning>,
+}
+
 #[derive(Debug)]
 pub struct GradeResultsTableType<'a> {
     pub name_el: ElementRef<'a>,
@@ -347,17 +664,485 @@ pub struct GradeResultsTableType<'a> {
     pub akad_period_el: ElementRef<'a>,
 }
 
-impl<'a> From<&'a mut scraper::element_ref::Select<'a, 'a>> for GradeResultsTableType<'a> {
-    fn from(iter: &'a mut scraper::element_ref::Select<'a, 'a>) -> GradeResultsTableType<'a> {
-        GradeResultsTableType {
-            name_el: iter.next().unwrap(),
-            grade_el: iter.next().unwrap(),
-            passed_el: iter.next().unwrap(),
-            ects_el: iter.next().unwrap(),
-            beurteilung_el: iter.next().unwrap(),
-            bekanntgabe_el: iter.next().unwrap(),
-            wiederholung_el: iter.next().unwrap(),
-            akad_period_el: iter.next().unwrap(),
-        }
+// Err contains the name of the first missing column
+impl<'a> TryFrom<&'a mut scraper::element_ref::Select<'a, 'a>> for GradeResultsTableType<'a> {
+    type Error = &'static str;
+
+    fn try_from(
+        iter: &'a mut scraper::element_ref::Select<'a, 'a>,
+    ) -> Result<GradeResultsTableType<'a>, Self::Error> {
+        Ok(GradeResultsTableType {
+            name_el: iter.next().ok_or("name")?,
+            grade_el: iter.next().ok_or("grade")?,
+            passed_el: iter.next().ok_or("passed")?,
+            ects_el: iter.next().ok_or("credit_points")?,
+            beurteilung_el: iter.next().ok_or("beurteilung")?,
+            bekanntgabe_el: iter.next().ok_or("bekanntgabe")?,
+            wiederholung_el: iter.next().ok_or("wiederholung")?,
+            akad_period_el: iter.next().ok_or("akad_period")?,
+        })
     }
 }
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ScheduledExam {
+    pub name: String,
+    pub verfahren: String,
+    pub registered: bool,
+    pub start: DateTime<Tz>,
+    pub end: DateTime<Tz>,
+    // true if CampusDual did not publish an end time and the default exam length was assumed
+    pub end_estimated: bool,
+    pub room: Option<ExamRoom>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExamConflict {
+    ExamOverlap {
+        first: String,
+        second: String,
+    },
+    LectureClash {
+        exam: String,
+        lecture: String,
+        lecture_start: DateTime<Tz>,
+        lecture_end: DateTime<Tz>,
+        lecture_room: String,
+    },
+    BackToBack {
+        first: String,
+        second: String,
+        gap_minutes: i64,
+        first_room: String,
+        second_room: String,
+    },
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExamConflictReport {
+    pub exams: Vec<ScheduledExam>,
+    pub conflicts: Vec<ExamConflict>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExamCalendarFormat {
+    #[default]
+    Ics,
+    Json,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExamCalendarQuery {
+    #[serde(default)]
+    pub format: ExamCalendarFormat,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExamCalendarEventKind {
+    Exam,
+    RegistrationBegin,
+    RegistrationEnd,
+    DeregistrationEnd,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExamCalendarEvent {
+    pub uid: String,
+    pub kind: ExamCalendarEventKind,
+    pub title: String,
+    pub exam_name: String,
+    pub registered: bool,
+    pub start: DateTime<Tz>,
+    // deadlines are points in time and have no end
+    pub end: Option<DateTime<Tz>>,
+    pub location: Option<String>,
+    pub alarm_minutes_before: Vec<i64>,
+    pub internal_metadata: Option<ExamRegistrationMetadata>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadlineSource {
+    ExamSignup,
+    ExamCancel,
+    ExamDetails,
+    Reminders,
+    Timeline,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadlineKind {
+    Exam,
+    RegistrationBegin,
+    RegistrationEnd,
+    DeregistrationEnd,
+    Appointment,
+    SemesterStart,
+    SemesterEnd,
+    Special,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Deadline {
+    pub source: DeadlineSource,
+    pub kind: DeadlineKind,
+    pub title: String,
+    pub due: DateTime<Tz>,
+    pub assessment: Option<ExamRegistrationMetadata>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeadlinesQuery {
+    #[serde(default)]
+    pub include_past: bool,
+    // only deadlines within the next n days
+    pub days: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InstructorsQuery {
+    // timetable days ahead that are searched for sessions
+    pub days: Option<i64>,
+}
+
+// Aggregated from the timetable, the dashboard reminders and the exam offers
+#[derive(Serialize, Debug)]
+pub struct Instructor {
+    pub name: String,
+    pub short_names: Vec<String>,
+    pub modules: Vec<InstructorModule>,
+    pub upcoming_session_count: usize,
+    // only the next few
+    pub upcoming_sessions: Vec<InstructorSession>,
+    pub exams: Vec<InstructorExam>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InstructorModule {
+    pub code: Option<String>,
+    pub title: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InstructorSession {
+    pub title: String,
+    pub start: DateTime<Tz>,
+    pub end: Option<DateTime<Tz>>,
+    pub room: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InstructorExam {
+    pub name: String,
+    pub registered: bool,
+    pub date: Option<NaiveDate>,
+    pub start: Option<NaiveTime>,
+    pub internal_metadata: Option<ExamRegistrationMetadata>,
+}
+
+// Inclusive date range, defaults to the current week (Monday to Sunday)
+#[derive(Deserialize, Debug, Default)]
+pub struct StundenplanQuery {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+    #[serde(default)]
+    pub palette: ColorPalette,
+    // module colors of the user, "<module code or title>:<hex>" separated by commas
+    pub colors: Option<String>,
+    // filters, comma separated; modules match the code or a part of the title
+    pub modules: Option<String>,
+    pub exclude_modules: Option<String>,
+    pub instructors: Option<String>,
+    pub exclude_instructors: Option<String>,
+    pub rooms: Option<String>,
+    pub exclude_rooms: Option<String>,
+    // "lecture", "exam" and/or "cancelled"
+    pub types: Option<String>,
+    pub exclude_types: Option<String>,
+    // modules from /timetable_exemptions are hidden unless this is set
+    #[serde(default)]
+    pub show_exempt: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorPalette {
+    // colors hashed from the title, as before palettes existed
+    #[default]
+    Classic,
+    Bright,
+    ColorblindSafe,
+    Dark,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimetableEventStatus {
+    Regular,
+    Changed,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimetableRoom {
+    pub short: String,
+    pub long: String,
+    pub location: Option<RoomLocation>,
+}
+
+// Content of ROOM_DIRECTORY_FILE (TOML or JSON), codes are matched case and whitespace insensitive
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RoomDirectoryData {
+    #[serde(default)]
+    pub buildings: Vec<BuildingInfo>,
+    #[serde(default)]
+    pub rooms: Vec<RoomInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BuildingInfo {
+    // "A", "5", the part of a room code before the first separator
+    pub code: String,
+    pub name: Option<String>,
+    pub campus: Option<String>,
+    pub address: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoomInfo {
+    pub code: String,
+    // other spellings CampusDual uses for the same room ("Hörsaal 5.101")
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub name: Option<String>,
+    // defaults to the building code of the room code
+    pub building: Option<String>,
+    // 0 is the ground floor
+    pub floor: Option<i32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+// attached to timetable and exam rooms, rooms missing in the directory only get their building
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoomLocation {
+    pub code: Option<String>,
+    pub name: Option<String>,
+    pub building: Option<BuildingInfo>,
+    pub floor: Option<i32>,
+    // of the room if known, otherwise of the building
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RoomsQuery {
+    // part of a room code, alias or name
+    pub search: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimetableEventType {
+    Lecture,
+    Exam,
+    Cancelled,
+}
+
+// modules (code or title) the user got recognised and never attends
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TimetableExemptions {
+    pub modules: Vec<String>,
+}
+
+// StundenplanItem with typed and split up fields
+#[derive(Serialize, Debug, Clone)]
+pub struct TimetableEvent {
+    pub start: DateTime<Tz>,
+    pub end: DateTime<Tz>,
+    pub all_day: bool,
+    pub title: String,
+    pub module_code: Option<String>,
+    pub module_title: String,
+    pub description: String,
+    pub room: Option<TimetableRoom>,
+    pub instructors: Vec<String>,
+    pub instructors_short: Vec<String>,
+    pub remarks: Option<String>,
+    pub status: TimetableEventStatus,
+    // CampusDual colors some events (exams, short notice changes) darkred
+    pub highlighted: bool,
+    pub color: String,
+    pub font_color: String,
+}
+
+// What a client keeps between two /timetable_changes calls
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimetableSnapshotEntry {
+    pub title: String,
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+    pub room: Option<String>,
+    pub status: TimetableEventStatus,
+    // CampusDual colors some events (exams, short notice changes) darkred
+    pub highlighted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimetableChange {
+    Added {
+        event: TimetableSnapshotEntry,
+    },
+    Removed {
+        event: TimetableSnapshotEntry,
+    },
+    Moved {
+        event: TimetableSnapshotEntry,
+        previous_start: DateTime<FixedOffset>,
+        previous_end: DateTime<FixedOffset>,
+        previous_room: Option<String>,
+    },
+    RoomChanged {
+        event: TimetableSnapshotEntry,
+        previous_room: Option<String>,
+    },
+    StatusChanged {
+        event: TimetableSnapshotEntry,
+        previous_status: TimetableEventStatus,
+    },
+    Highlighted {
+        event: TimetableSnapshotEntry,
+    },
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct TimetableChangesRequest {
+    pub days: Option<i64>,
+    pub snapshot: Option<Vec<TimetableSnapshotEntry>>,
+    // enough to find out if anything changed at all, without sending the snapshot
+    pub fingerprint: Option<String>,
+    // the horizon returned along with the snapshot
+    pub horizon: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimetableChangesResponse {
+    pub fingerprint: String,
+    pub unchanged: bool,
+    pub changes: Vec<TimetableChange>,
+    pub snapshot: Vec<TimetableSnapshotEntry>,
+    // end of the time the snapshot covers, send it back with the snapshot
+    pub horizon: DateTime<FixedOffset>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TimetableWatchRequest {
+    pub days: Option<i64>,
+    pub webhook_url: Option<String>,
+    pub email: Option<String>,
+}
+
+// Day bounds are local times ("08:00"), the range works like StundenplanQuery
+#[derive(Deserialize, Debug, Default)]
+pub struct TimetableAnalysisQuery {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+    pub day_start: Option<String>,
+    pub day_end: Option<String>,
+    pub min_slot_minutes: Option<i64>,
+    #[serde(default)]
+    pub include_weekends: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimetableFreeSlot {
+    pub start: DateTime<Tz>,
+    pub end: DateTime<Tz>,
+    pub minutes: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimetableDayAnalysis {
+    pub date: NaiveDate,
+    pub first_lecture: Option<DateTime<Tz>>,
+    pub last_lecture_end: Option<DateTime<Tz>>,
+    // parallel lectures are only counted once
+    pub contact_minutes: i64,
+    pub free_slots: Vec<TimetableFreeSlot>,
+    pub all_day_events: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimetableWeekWorkload {
+    pub iso_year: i32,
+    pub iso_week: u32,
+    pub monday: NaiveDate,
+    pub contact_hours: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimetableModuleWorkload {
+    pub module_code: Option<String>,
+    pub module_title: String,
+    pub lectures: usize,
+    pub contact_hours: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimetableAnalysis {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub day_start: NaiveTime,
+    pub day_end: NaiveTime,
+    pub total_contact_hours: f64,
+    pub days: Vec<TimetableDayAnalysis>,
+    pub free_days: Vec<NaiveDate>,
+    pub weeks: Vec<TimetableWeekWorkload>,
+    pub modules: Vec<TimetableModuleWorkload>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TimetableExportFormat {
+    Pdf,
+    Png,
+}
+
+// width/height are points for PDF (default A4 landscape) and pixels for PNG
+#[derive(Deserialize, Debug)]
+pub struct TimetableExportQuery {
+    pub format: TimetableExportFormat,
+    // any day of the week, defaults to the current week
+    pub week: Option<NaiveDate>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub weekend: bool,
+    #[serde(default)]
+    pub palette: ColorPalette,
+    pub colors: Option<String>,
+}
+
+// Encrypted into the CalDAV app password, only valid for /caldav
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CalDavAppPassword {
+    pub scope: String,
+    pub user: String,
+    pub password: String,
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CalDavPasswordResponse {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    pub expires: DateTime<Utc>,
+}